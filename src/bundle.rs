@@ -0,0 +1,242 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use config::Config;
+use error::InstallError;
+use lockfile::{Lockfile, LOCKFILE_NAME};
+
+// A bundle is a .tar.gz holding everything an install would fetch, for machines with no
+// network access:
+//   sky-install.lock  what the bundle holds, as 'lock' would write it
+//   SCAII/            the checkout, with Sky-RTS and the viz javascript, minus build output
+//   vendor/           the crates SCAII and Sky-RTS depend on, from 'cargo vendor'
+//   .cargo/config     the sources 'cargo vendor' says replace crates.io and each git
+//                     dependency, pointed at vendor/
+// install --from-bundle unpacks it into the version's git dir, where cargo finds
+// .cargo/config in a parent of the dirs it builds in.
+pub const BUNDLE_SCAII_DIRNAME: &'static str = "SCAII";
+pub const BUNDLE_VENDOR_DIRNAME: &'static str = "vendor";
+const CARGO_CONFIG_PATH: &'static str = ".cargo/config";
+
+// used for bundles made before they carried their own .cargo/config, which only had
+// crates.io dependencies replaced
+const CARGO_VENDOR_CONFIG: &'static str = "[source.crates-io]
+replace-with = \"vendored-sources\"
+
+[source.vendored-sources]
+directory = \"vendor\"
+";
+
+// writes a bundle of the checkout at scaii_dir, which lock describes, to out_path.
+// work_dir is scratch space for the vendored crates.
+pub fn create(
+    lock: &Lockfile,
+    scaii_dir: &PathBuf,
+    work_dir: &PathBuf,
+    out_path: &PathBuf,
+) -> Result<(), Box<Error>> {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use platform;
+    use tar::{Builder, Header};
+    use toml;
+
+    if work_dir.as_path().exists() {
+        platform::remove_tree(work_dir)?;
+    }
+    fs::create_dir_all(work_dir)?;
+    let vendor_dir = work_dir.join(BUNDLE_VENDOR_DIRNAME);
+    let cargo_config = vendor_crates(scaii_dir, &vendor_dir)?;
+
+    println!("...writing {:?}", out_path);
+    let out_file = fs::File::create(out_path)?;
+    let mut builder = Builder::new(GzEncoder::new(out_file, Compression::default()));
+    let lock_contents = toml::to_string(lock)?;
+    let mut header = Header::new_gnu();
+    header.set_size(lock_contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, LOCKFILE_NAME, lock_contents.as_bytes())?;
+    let mut header = Header::new_gnu();
+    header.set_size(cargo_config.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, CARGO_CONFIG_PATH, cargo_config.as_bytes())?;
+    append_tree(&mut builder, scaii_dir, Path::new(BUNDLE_SCAII_DIRNAME))?;
+    append_tree(&mut builder, &vendor_dir, Path::new(BUNDLE_VENDOR_DIRNAME))?;
+    builder.into_inner()?.finish()?;
+    platform::remove_tree(work_dir)?;
+    Ok(())
+}
+
+// extra backends are cloned from their own repositories, which a bundle doesn't hold, so
+// neither creating nor installing a bundle goes ahead while any are configured
+pub fn refuse_extra_backends(config: &Config) -> Result<(), Box<Error>> {
+    if config.extra_backends.is_empty() {
+        return Ok(());
+    }
+    let names: Vec<&str> = config
+        .extra_backends
+        .iter()
+        .map(|backend| backend.name.as_ref())
+        .collect();
+    Err(Box::new(InstallError::Usage(format!(
+        "bundles can't hold extra_backends, and {} are configured - remove them from \
+         config.toml to create or install a bundle.",
+        names.join(", ")
+    ))))
+}
+
+// unpacks the bundle at bundle_path into install_dir, returning the lockfile describing it
+pub fn unpack(bundle_path: &PathBuf, install_dir: &PathBuf) -> Result<Lockfile, Box<Error>> {
+    use platform::common::extract_archive;
+    use std::io::Write;
+
+    if !bundle_path.as_path().exists() {
+        return Err(Box::new(InstallError::Prerequisite(format!(
+            "bundle {:?} not found.",
+            bundle_path
+        ))));
+    }
+    println!("...unpacking {:?}", bundle_path);
+    extract_archive(bundle_path, install_dir, 0, "")?;
+    let lock_path = install_dir.join(LOCKFILE_NAME);
+    if !lock_path.as_path().exists() || !install_dir.join(BUNDLE_SCAII_DIRNAME).exists() {
+        return Err(Box::new(InstallError::Usage(format!(
+            "{:?} is not a sky-install bundle - create one with 'bundle create'.",
+            bundle_path
+        ))));
+    }
+    let lock = Lockfile::load(&lock_path)?;
+    fs::remove_file(&lock_path)?;
+
+    // cargo looks for .cargo/config in every parent of the dir it builds in, and reads
+    // the directory named there relative to the dir holding .cargo
+    let cargo_dir = install_dir.join(".cargo");
+    if !cargo_dir.join("config").exists() {
+        fs::create_dir_all(&cargo_dir)?;
+        let mut config_file = fs::File::create(cargo_dir.join("config"))?;
+        config_file.write_all(CARGO_VENDOR_CONFIG.as_bytes())?;
+    }
+    Ok(lock)
+}
+
+// copies the crates.io and git dependencies of SCAII and of the Sky-RTS backend into
+// vendor_dir, returning the cargo config that builds from them.  cargo vendor prints it,
+// with a [source] replacing each git dependency as well as crates.io.
+fn vendor_crates(scaii_dir: &PathBuf, vendor_dir: &PathBuf) -> Result<String, Box<Error>> {
+    use platform::common::capture_command;
+
+    let sky_rts_manifest = scaii_dir
+        .join("backends")
+        .join("sky-rts")
+        .join("backend")
+        .join("Cargo.toml");
+    let command: String = "cargo".to_string();
+    let mut args: Vec<String> = Vec::new();
+    args.push("vendor".to_string());
+    args.push("--manifest-path".to_string());
+    args.push(scaii_dir.join("Cargo.toml").to_string_lossy().to_string());
+    if sky_rts_manifest.as_path().exists() {
+        args.push("--sync".to_string());
+        args.push(sky_rts_manifest.to_string_lossy().to_string());
+    }
+    args.push(vendor_dir.to_string_lossy().to_string());
+    let printed_config = match capture_command(&command, args) {
+        Ok(printed_config) => printed_config,
+        Err(err) => {
+            return Err(Box::new(InstallError::Prerequisite(format!(
+                "ERROR - cargo vendor failed: {} - before Rust 1.37 it has to be installed with \
+                 'cargo install cargo-vendor'.",
+                err
+            ))))
+        }
+    };
+    if !printed_config.contains("[source.vendored-sources]") {
+        return Ok(CARGO_VENDOR_CONFIG.to_string());
+    }
+    Ok(relative_vendor_config(&printed_config))
+}
+
+// cargo vendor names vendor_dir by its full path in the work dir, which is gone once the
+// bundle is written, so the config points at vendor/ beside .cargo instead
+fn relative_vendor_config(printed_config: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    for line in printed_config.lines() {
+        if line.trim_start().starts_with("directory") && line.contains('=') {
+            lines.push(format!("directory = \"{}\"", BUNDLE_VENDOR_DIRNAME));
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+// adds dir to the archive as name, leaving out cargo's target dirs
+fn append_tree<W: ::std::io::Write>(
+    builder: &mut ::tar::Builder<W>,
+    dir: &PathBuf,
+    name: &Path,
+) -> Result<(), Box<Error>> {
+    builder.append_path_with_name(dir, name)?;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let entry_name = name.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if entry.file_name() == "target" && dir.join("Cargo.toml").exists() {
+                continue;
+            }
+            append_tree(builder, &path, &entry_name)?;
+        } else {
+            builder.append_path_with_name(&path, &entry_name)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vendor_config_keeps_git_sources_and_points_at_bundled_vendor_dir() {
+        let printed = "[source.crates-io]
+replace-with = \"vendored-sources\"
+
+[source.\"git+https://github.com/SCAII/example\"]
+git = \"https://github.com/SCAII/example\"
+rev = \"0123abc\"
+replace-with = \"vendored-sources\"
+
+[source.vendored-sources]
+directory = \"/tmp/work/vendor\"
+";
+        let config = relative_vendor_config(printed);
+        assert!(config.contains("[source.\"git+https://github.com/SCAII/example\"]"));
+        assert!(config.contains("rev = \"0123abc\""));
+        assert!(config.contains("directory = \"vendor\"\n"));
+        assert!(!config.contains("/tmp/work"));
+    }
+
+    #[test]
+    fn extra_backends_keep_bundles_from_being_used() {
+        use config::ExtraBackend;
+
+        let mut config = Config::default();
+        assert!(refuse_extra_backends(&config).is_ok());
+        let mut backend = ExtraBackend::default();
+        backend.name = "my-backend".to_string();
+        config.extra_backends.push(backend);
+        match refuse_extra_backends(&config) {
+            Err(err) => match err.downcast_ref::<InstallError>() {
+                Some(&InstallError::Usage(ref details)) => assert!(details.contains("my-backend")),
+                _ => panic!("expected a usage error, got {}", err),
+            },
+            Ok(()) => panic!("accepted configured extra_backends"),
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use config::Config;
+use error::InstallError;
+
+// Downloaded archives are kept in ~/.scaii/cache (or the cache_dir setting) so that
+// installing another version, or reinstalling after an uninstall, doesn't fetch them
+// again.  Each archive is stored under the SHA-256 of its contents, as
+// cache/<sha256>/<filename>, and cache/index.toml records which url it came from and
+// when it was last used.
+pub const CACHE_DIRNAME: &'static str = "cache";
+const INDEX_FILENAME: &'static str = "index.toml";
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheIndex {
+    #[serde(default, rename = "entry")]
+    entries: Vec<CacheEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    url: String,
+    sha256: String,
+    filename: String,
+    // seconds since the unix epoch
+    downloaded: u64,
+    last_used: u64,
+}
+
+impl CacheIndex {
+    fn load(cache_dir: &PathBuf) -> Result<CacheIndex, Box<Error>> {
+        use std::io::Read;
+        use toml;
+
+        let path = cache_dir.join(INDEX_FILENAME);
+        if !path.as_path().exists() {
+            return Ok(CacheIndex::default());
+        }
+        let mut contents = String::new();
+        fs::File::open(&path)?.read_to_string(&mut contents)?;
+        let index: CacheIndex = toml::from_str(&contents)?;
+        Ok(index)
+    }
+
+    fn save(&self, cache_dir: &PathBuf) -> Result<(), Box<Error>> {
+        use std::io::Write;
+        use toml;
+
+        fs::create_dir_all(cache_dir)?;
+        let contents = toml::to_string(self)?;
+        let mut file = fs::File::create(cache_dir.join(INDEX_FILENAME))?;
+        file.write_all(contents.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl CacheEntry {
+    fn path(&self, cache_dir: &PathBuf) -> PathBuf {
+        cache_dir.join(&self.sha256).join(&self.filename)
+    }
+}
+
+pub fn get_cache_dir(config: &Config) -> PathBuf {
+    match config.cache_dir {
+        Some(ref cache_dir) => PathBuf::from(cache_dir),
+        None => config.dot_scaii.join(CACHE_DIRNAME),
+    }
+}
+
+// false when config.toml turns the cache off
+pub fn enabled(config: &Config) -> bool {
+    config.cache.unwrap_or(true)
+}
+
+// the cached download of url and its SHA-256, if there is one with the expected SHA-256
+// (or any, when nothing is expected yet).  A cached file that no longer has the digest it was stored
+// under is dropped rather than returned.
+pub fn lookup(
+    config: &Config,
+    url: &str,
+    expected_sha256: Option<&str>,
+) -> Result<Option<(PathBuf, String)>, Box<Error>> {
+    use platform::common::sha256_file;
+
+    let cache_dir = get_cache_dir(config);
+    let mut index = CacheIndex::load(&cache_dir)?;
+    let position = index.entries.iter().position(|entry| {
+        entry.url == url && expected_sha256.map_or(true, |sha256| entry.sha256 == sha256)
+    });
+    let position = match position {
+        Some(position) => position,
+        None => return Ok(None),
+    };
+    let path = index.entries[position].path(&cache_dir);
+    if !path.as_path().exists() || sha256_file(&path)? != index.entries[position].sha256 {
+        println!("...discarding damaged cache entry {:?}", path);
+        let entry = index.entries.remove(position);
+        remove_entry_file(&cache_dir, &entry, &index.entries)?;
+        index.save(&cache_dir)?;
+        return Ok(None);
+    }
+    index.entries[position].last_used = now_secs();
+    let sha256 = index.entries[position].sha256.clone();
+    index.save(&cache_dir)?;
+    Ok(Some((path, sha256)))
+}
+
+// moves a verified download of url into the cache, returning where it now lives
+pub fn store(
+    config: &Config,
+    url: &str,
+    download_path: &PathBuf,
+    sha256: &str,
+) -> Result<PathBuf, Box<Error>> {
+    let cache_dir = get_cache_dir(config);
+    let mut index = CacheIndex::load(&cache_dir)?;
+    let filename = match download_path.file_name() {
+        Some(filename) => filename.to_string_lossy().to_string(),
+        None => {
+            return Err(Box::new(InstallError::filesystem(
+                download_path,
+                "can't cache the download - it has no file name".to_string(),
+            )))
+        }
+    };
+    let now = now_secs();
+    let entry = CacheEntry {
+        url: url.to_string(),
+        sha256: sha256.to_string(),
+        filename: filename,
+        downloaded: now,
+        last_used: now,
+    };
+    let path = entry.path(&cache_dir);
+    fs::create_dir_all(cache_dir.join(&entry.sha256))?;
+    // the cache is normally on the same filesystem as the install, but not with --prefix
+    if fs::rename(download_path, &path).is_err() {
+        fs::copy(download_path, &path)?;
+        fs::remove_file(download_path)?;
+    }
+    index
+        .entries
+        .retain(|existing| !(existing.url == entry.url && existing.sha256 == entry.sha256));
+    index.entries.push(entry);
+    index.save(&cache_dir)?;
+    Ok(path)
+}
+
+pub fn list(config: &Config) -> Result<(), Box<Error>> {
+    let cache_dir = get_cache_dir(config);
+    let index = CacheIndex::load(&cache_dir)?;
+    if index.entries.is_empty() {
+        println!("The download cache in {:?} is empty.", cache_dir);
+        return Ok(());
+    }
+    let now = now_secs();
+    let mut total = 0;
+    for entry in index.entries.iter() {
+        let size = match fs::metadata(entry.path(&cache_dir)) {
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        };
+        total += size;
+        println!(
+            "{}  {:>8.1} MiB  last used {} days ago\n    sha256 {}",
+            entry.url,
+            size as f64 / 1048576.0,
+            (now - entry.last_used.min(now)) / SECS_PER_DAY,
+            entry.sha256
+        );
+    }
+    println!(
+        "{} cached downloads, {:.1} MiB in {:?}",
+        index.entries.len(),
+        total as f64 / 1048576.0,
+        cache_dir
+    );
+    Ok(())
+}
+
+pub fn clean(config: &Config) -> Result<(), Box<Error>> {
+    use platform;
+
+    let cache_dir = get_cache_dir(config);
+    if cache_dir.as_path().exists() {
+        platform::remove_tree(&cache_dir)?;
+    }
+    println!("Emptied the download cache in {:?}.", cache_dir);
+    Ok(())
+}
+
+// removes the downloads that haven't been used for older_than_days
+pub fn prune(config: &Config, older_than_days: u64) -> Result<(), Box<Error>> {
+    let cache_dir = get_cache_dir(config);
+    let mut index = CacheIndex::load(&cache_dir)?;
+    let age_secs = match older_than_days.checked_mul(SECS_PER_DAY) {
+        Some(age_secs) => age_secs,
+        None => {
+            return Err(Box::new(InstallError::Usage(format!(
+                "--older-than {} days is too long",
+                older_than_days
+            ))))
+        }
+    };
+    let cutoff = now_secs().saturating_sub(age_secs);
+    let (old, kept): (Vec<CacheEntry>, Vec<CacheEntry>) = index
+        .entries
+        .into_iter()
+        .partition(|entry| entry.last_used < cutoff);
+    for entry in old.iter() {
+        println!("...removing {}", entry.url);
+        remove_entry_file(&cache_dir, entry, &kept)?;
+    }
+    index.entries = kept;
+    index.save(&cache_dir)?;
+    println!(
+        "Removed {} cached downloads unused for {} days.",
+        old.len(),
+        older_than_days
+    );
+    Ok(())
+}
+
+// reads the --older-than value: a number of days, optionally followed by 'd', or of
+// weeks followed by 'w'
+pub fn parse_age_days(age: &str) -> Result<u64, Box<Error>> {
+    let (number, multiplier) = if age.ends_with('w') {
+        (&age[..age.len() - 1], 7)
+    } else if age.ends_with('d') {
+        (&age[..age.len() - 1], 1)
+    } else {
+        (age, 1)
+    };
+    let days = match number.parse::<u64>() {
+        Ok(number) => number.checked_mul(multiplier),
+        Err(_) => {
+            return Err(Box::new(InstallError::Usage(format!(
+                "--older-than expects a number of days like 30 or 30d, or weeks like 4w, not '{}'",
+                age
+            ))))
+        }
+    };
+    // prune works in seconds, so the age must fit in them as well
+    match days {
+        Some(days) if days.checked_mul(SECS_PER_DAY).is_some() => Ok(days),
+        _ => Err(Box::new(InstallError::Usage(format!(
+            "--older-than {} is too long",
+            age
+        )))),
+    }
+}
+
+// removes the file of an entry dropped from the index, unless a remaining entry for
+// another url shares it
+fn remove_entry_file(
+    cache_dir: &PathBuf,
+    entry: &CacheEntry,
+    remaining: &Vec<CacheEntry>,
+) -> Result<(), Box<Error>> {
+    use platform;
+
+    let entry_path = entry.path(cache_dir);
+    if remaining
+        .iter()
+        .any(|other| other.path(cache_dir) == entry_path)
+    {
+        return Ok(());
+    }
+    if entry_path.as_path().exists() {
+        fs::remove_file(&entry_path)?;
+    }
+    let entry_dir = cache_dir.join(&entry.sha256);
+    if entry_dir.as_path().exists() && fs::read_dir(&entry_dir)?.next().is_none() {
+        platform::remove_tree(&entry_dir)?;
+    }
+    Ok(())
+}
+
+fn now_secs() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs(),
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_days_and_weeks() {
+        assert_eq!(parse_age_days("30").unwrap(), 30);
+        assert_eq!(parse_age_days("30d").unwrap(), 30);
+        assert_eq!(parse_age_days("4w").unwrap(), 28);
+        assert_eq!(parse_age_days("0").unwrap(), 0);
+    }
+
+    #[test]
+    fn rejects_what_is_not_an_age() {
+        for age in ["", "w", "-3", "3x", "3.5d", "d4"].iter() {
+            assert!(parse_age_days(age).is_err(), "accepted {:?}", age);
+        }
+    }
+
+    #[test]
+    fn rejects_ages_too_long_to_count_in_seconds() {
+        assert!(parse_age_days("99999999999999999w").is_err());
+        assert!(parse_age_days("18446744073709551615").is_err());
+        let max_days = ::std::u64::MAX / SECS_PER_DAY;
+        assert_eq!(parse_age_days(&max_days.to_string()).unwrap(), max_days);
+        assert!(parse_age_days(&(max_days + 1).to_string()).is_err());
+    }
+}
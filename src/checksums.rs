@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+
+use config::Config;
+
+// Every archive the installer downloads is checked against an expected SHA-256 before it
+// is unpacked.  The digest comes from config.toml's [checksums] table, else the built-in
+// table below, else the digest recorded the first time the url was downloaded, which is
+// kept in ~/.scaii/checksums.toml so that a download that changes later is caught.  A
+// recorded digest never stands in for a built-in one.
+pub const CHECKSUMS_FILENAME: &'static str = "checksums.toml";
+
+// digests confirmed against a trusted download, as (url, sha256).  Urls not listed here
+// are trusted on first download and recorded.  The default closure-library and protobuf
+// archives belong here once their digests have been confirmed.
+const BUILTIN_SHA256: &'static [(&'static str, &'static str)] = &[];
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RecordedChecksums {
+    #[serde(default)]
+    sha256: BTreeMap<String, String>,
+}
+
+impl RecordedChecksums {
+    fn load(config: &Config) -> Result<RecordedChecksums, Box<Error>> {
+        use std::io::Read;
+        use toml;
+
+        let path = config.dot_scaii.join(CHECKSUMS_FILENAME);
+        if !path.as_path().exists() {
+            return Ok(RecordedChecksums::default());
+        }
+        let mut contents = String::new();
+        fs::File::open(&path)?.read_to_string(&mut contents)?;
+        let recorded: RecordedChecksums = toml::from_str(&contents)?;
+        Ok(recorded)
+    }
+
+    fn save(&self, config: &Config) -> Result<(), Box<Error>> {
+        use std::io::Write;
+        use toml;
+
+        let contents = toml::to_string(self)?;
+        let mut file = fs::File::create(config.dot_scaii.join(CHECKSUMS_FILENAME))?;
+        file.write_all(contents.as_bytes())?;
+        Ok(())
+    }
+}
+
+// the digest the download of url must have, or None if it has never been downloaded
+pub fn expected_sha256(config: &Config, url: &str) -> Result<Option<String>, Box<Error>> {
+    expected_sha256_from(BUILTIN_SHA256, config, url)
+}
+
+fn expected_sha256_from(
+    builtin: &[(&str, &str)],
+    config: &Config,
+    url: &str,
+) -> Result<Option<String>, Box<Error>> {
+    if let Some(sha256) = config.checksums.get(url) {
+        return Ok(Some(sha256.to_lowercase()));
+    }
+    for &(builtin_url, sha256) in builtin.iter() {
+        if builtin_url == url {
+            return Ok(Some(sha256.to_string()));
+        }
+    }
+    let recorded = RecordedChecksums::load(config)?;
+    Ok(recorded.sha256.get(url).cloned())
+}
+
+// remembers the digest of a first download of url, so later downloads must match it
+pub fn record_sha256(config: &Config, url: &str, sha256: &str) -> Result<(), Box<Error>> {
+    let mut recorded = RecordedChecksums::load(config)?;
+    if !recorded.sha256.contains_key(url) {
+        println!("recording SHA-256 {} for {}", sha256, url);
+        recorded.sha256.insert(url.to_string(), sha256.to_string());
+        recorded.save(config)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    const URL: &'static str = "https://example.com/archive/v1.zip";
+    const GOOD_SHA256: &'static str =
+        "0000000000000000000000000000000000000000000000000000000000000001";
+    const TAMPERED_SHA256: &'static str =
+        "0000000000000000000000000000000000000000000000000000000000000002";
+
+    fn scratch_config(test: &str) -> Config {
+        let mut config = Config::default();
+        config.dot_scaii =
+            env::temp_dir().join(format!("sky-install-{}-{}", ::std::process::id(), test));
+        if config.dot_scaii.as_path().exists() {
+            fs::remove_dir_all(&config.dot_scaii).unwrap();
+        }
+        fs::create_dir_all(&config.dot_scaii).unwrap();
+        config
+    }
+
+    #[test]
+    fn builtin_digest_outranks_one_recorded_on_first_download() {
+        let config = scratch_config("checksums-builtin");
+        let builtin = [(URL, GOOD_SHA256)];
+        record_sha256(&config, URL, TAMPERED_SHA256).unwrap();
+        assert_eq!(
+            expected_sha256_from(&builtin, &config, URL).unwrap(),
+            Some(GOOD_SHA256.to_string())
+        );
+        fs::remove_dir_all(&config.dot_scaii).unwrap();
+    }
+
+    #[test]
+    fn configured_digest_overrides_builtin() {
+        let mut config = scratch_config("checksums-configured");
+        let builtin = [(URL, GOOD_SHA256)];
+        config
+            .checksums
+            .insert(URL.to_string(), TAMPERED_SHA256.to_uppercase());
+        assert_eq!(
+            expected_sha256_from(&builtin, &config, URL).unwrap(),
+            Some(TAMPERED_SHA256.to_string())
+        );
+        fs::remove_dir_all(&config.dot_scaii).unwrap();
+    }
+
+    #[test]
+    fn other_urls_are_trusted_on_first_download_only() {
+        let config = scratch_config("checksums-recorded");
+        assert_eq!(expected_sha256_from(&[], &config, URL).unwrap(), None);
+        record_sha256(&config, URL, GOOD_SHA256).unwrap();
+        record_sha256(&config, URL, TAMPERED_SHA256).unwrap();
+        assert_eq!(
+            expected_sha256_from(&[], &config, URL).unwrap(),
+            Some(GOOD_SHA256.to_string())
+        );
+        fs::remove_dir_all(&config.dot_scaii).unwrap();
+    }
+}
@@ -0,0 +1,350 @@
+use clap::{App, AppSettings, Arg, ArgMatches, Error, SubCommand};
+
+use Args;
+
+// The command line is described to clap, which prints the help for sky-install and each
+// of its commands, checks what it is given and exits with an error on anything it doesn't
+// expect.  parse_args then turns what was matched into the Args the commands read.
+const PROFILES: &'static [&'static str] = &["debug", "release"];
+
+pub fn app() -> App<'static, 'static> {
+    App::new("sky-install")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Installs SCAII, the Sky-RTS backend and the viz.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(
+            Arg::with_name("prefix")
+                .long("prefix")
+                .value_name("dir")
+                .global(true)
+                .help(
+                    "Installs into <dir> instead of ~/.scaii.  The SCAII_HOME environment \
+                     variable does the same when --prefix is not given.",
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("install")
+                .about(
+                    "Performs a clean installation of SCAII and Sky-RTS.  Each branch and \
+                     profile is installed side by side as its own version, which becomes the \
+                     active one.",
+                )
+                .arg(branch_arg().help(
+                    "Branch to install, from the branch setting or master when not given.  \
+                     With --source, the profile instead.",
+                ))
+                .arg(profile_arg().help("Build profile, release when not given."))
+                .arg(rev_arg().help(
+                    "Installs SCAII at a branch, tag or commit SHA.  A new version is named \
+                     after it unless a branch is given.",
+                ))
+                .arg(
+                    Arg::with_name("sky-rts-rev")
+                        .long("sky-rts-rev")
+                        .value_name("rev")
+                        .help("Does the same for Sky-RTS when it is a submodule of SCAII."),
+                )
+                .arg(
+                    Arg::with_name("source")
+                        .long("source")
+                        .value_name("path")
+                        .help("Builds the given SCAII checkout instead of cloning one."),
+                )
+                .arg(
+                    Arg::with_name("locked")
+                        .long("locked")
+                        .conflicts_with_all(&["source", "rev", "sky-rts-rev"])
+                        .help(
+                            "Installs exactly what sky-install.lock in the current directory \
+                             pins, failing if anything fetched doesn't match.",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("prebuilt")
+                        .long("prebuilt")
+                        .conflicts_with_all(&["source", "locked", "rev", "sky-rts-rev"])
+                        .help(
+                            "Downloads binaries built for this platform from the release \
+                             index instead of compiling SCAII, building from source when there \
+                             are none.",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("from-bundle")
+                        .long("from-bundle")
+                        .value_name("file")
+                        .conflicts_with_all(&["source", "locked", "rev", "sky-rts-rev", "prebuilt"])
+                        .help("Installs from a 'bundle create' archive without using the network."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("reinstall")
+                .about(
+                    "Quickly recompiles and reinstalls the active version without fetching \
+                     the latest version from Github.",
+                )
+                .arg(
+                    profile_arg()
+                        .index(1)
+                        .help("Build profile, release when not given."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("update")
+                .about(
+                    "Fetches the active version's checkout and fast-forwards it, or switches \
+                     it to the given branch, then rebuilds only what changed.",
+                )
+                .arg(branch_arg().help("Branch to switch to, the current one when not given."))
+                .arg(
+                    profile_arg()
+                        .requires("branch")
+                        .help("Build profile, the current one when not given."),
+                )
+                .arg(rev_arg().help("Updates SCAII to a branch, tag or commit SHA.")),
+        )
+        .subcommand(
+            SubCommand::with_name("uninstall")
+                .about("Uninstalls the named version, or every version if none is given.")
+                .arg(Arg::with_name("version").help("Version to uninstall.")),
+        )
+        .subcommand(
+            SubCommand::with_name("rollback")
+                .about("Restores the install that the last install or reinstall replaced."),
+        )
+        .subcommand(
+            SubCommand::with_name("use")
+                .about("Makes the named version the active one.")
+                .arg(
+                    Arg::with_name("version")
+                        .required(true)
+                        .help("Version to activate, as 'list' names it."),
+                ),
+        )
+        .subcommand(SubCommand::with_name("list").about("Lists installed versions."))
+        .subcommand(SubCommand::with_name("status").about(
+            "Shows the active version's branches, commits, profile and install time, \
+             whether its parts are present and unmodified, and whether its checkout has \
+             local modifications.",
+        ))
+        .subcommand(SubCommand::with_name("doctor").about(
+            "Checks that Rust, git, Python 3 and everything else an install and SCAII need \
+             are present, saying how to fix what isn't.  Install runs these checks first.",
+        ))
+        .subcommand(SubCommand::with_name("lock").about(
+            "Writes sky-install.lock in the current directory, pinning the commits and \
+             downloads of the active version.",
+        ))
+        .subcommand(
+            SubCommand::with_name("bundle")
+                .about("Creates archives for offline installs.")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("create")
+                        .about(
+                            "Packs the active version's checkout, viz javascript and vendored \
+                             crates into one archive.",
+                        )
+                        .arg(
+                            Arg::with_name("out")
+                                .required(true)
+                                .value_name("out.tar.gz")
+                                .help("Archive to write."),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about(
+                    "Shows or changes a setting.  Settings come from the command line, then \
+                     the environment, then ./sky-install.toml, then ~/.scaii/config.toml, \
+                     then the defaults.",
+                )
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("get")
+                        .about("Shows a setting and where it came from.")
+                        .arg(key_arg()),
+                )
+                .subcommand(
+                    SubCommand::with_name("set")
+                        .about("Writes a setting to ~/.scaii/config.toml.")
+                        .arg(key_arg())
+                        .arg(
+                            Arg::with_name("value")
+                                .required(true)
+                                .multiple(true)
+                                .help("Value to set.  Words are joined with spaces."),
+                        )
+                        .arg(
+                            Arg::with_name("project")
+                                .long("project")
+                                .help("Writes ./sky-install.toml instead."),
+                        ),
+                )
+                .subcommand(SubCommand::with_name("list").about("Shows every setting.")),
+        )
+        .subcommand(
+            SubCommand::with_name("cache")
+                .about("Manages the downloads kept in ~/.scaii/cache.")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("list").about("Lists the cached downloads."))
+                .subcommand(SubCommand::with_name("clean").about("Empties the cache."))
+                .subcommand(
+                    SubCommand::with_name("prune")
+                        .about("Removes downloads not used for the given time.")
+                        .arg(
+                            Arg::with_name("older-than")
+                                .long("older-than")
+                                .value_name("days")
+                                .required(true)
+                                .help("A number of days, as 30 or 30d, or of weeks, as 4w."),
+                        ),
+                ),
+        )
+}
+
+fn branch_arg() -> Arg<'static, 'static> {
+    Arg::with_name("branch").index(1)
+}
+
+fn profile_arg() -> Arg<'static, 'static> {
+    Arg::with_name("profile").index(2).possible_values(PROFILES)
+}
+
+fn rev_arg() -> Arg<'static, 'static> {
+    Arg::with_name("rev").long("rev").value_name("rev")
+}
+
+fn key_arg() -> Arg<'static, 'static> {
+    Arg::with_name("key")
+        .required(true)
+        .help("Setting name, with tables joined by dots as in repos.SCAII.url.")
+}
+
+// prints the help or version clap was asked for and exits, or prints what was wrong with
+// the command line and exits with the usage error code
+pub fn exit_with(err: Error) -> ! {
+    use error;
+    use std::process;
+
+    if err.use_stderr() {
+        eprintln!("{}", err.message);
+        process::exit(error::ErrorKind::Usage.exit_code());
+    }
+    println!("{}", err.message);
+    process::exit(0);
+}
+
+// the value of --prefix, which may come before or after the command
+pub fn prefix(matches: &ArgMatches) -> Option<String> {
+    let (_, sub_matches) = matches.subcommand();
+    sub_matches
+        .and_then(|sub_matches| sub_matches.value_of("prefix"))
+        .or(matches.value_of("prefix"))
+        .map(|prefix| prefix.to_string())
+}
+
+// the command and its Args.  Exits, as clap does, when the arguments don't fit together.
+pub fn parse_args(matches: &ArgMatches) -> (String, Args) {
+    use clap::ErrorKind;
+    use versions;
+
+    let mut args = Args {
+        flag_branch: false,
+        arg_branch_name: "".to_string(),
+        flag_profile: false,
+        arg_version_name: "".to_string(),
+        arg_source: "".to_string(),
+        arg_rev: "".to_string(),
+        arg_sky_rts_rev: "".to_string(),
+        flag_locked: false,
+        flag_prebuilt: false,
+        arg_cache_action: "".to_string(),
+        arg_older_than: "".to_string(),
+        arg_from_bundle: "".to_string(),
+        arg_bundle_action: "".to_string(),
+        arg_bundle_path: "".to_string(),
+        arg_config_action: "".to_string(),
+        arg_config_key: "".to_string(),
+        arg_config_value: "".to_string(),
+        flag_project: false,
+        compile_type: "".to_string(),
+    };
+    let (command, sub_matches) = match matches.subcommand() {
+        (command, Some(sub_matches)) => (command.to_string(), sub_matches),
+        // clap has already shown the help when there is no command
+        _ => unreachable!(),
+    };
+    let value = |name: &str| sub_matches.value_of(name).unwrap_or_default().to_string();
+
+    args.arg_rev = value("rev");
+    args.arg_sky_rts_rev = value("sky-rts-rev");
+    args.arg_source = value("source");
+    args.arg_from_bundle = value("from-bundle");
+    args.flag_locked = sub_matches.is_present("locked");
+    args.flag_prebuilt = sub_matches.is_present("prebuilt");
+    args.arg_version_name = value("version");
+
+    let mut branch = sub_matches.value_of("branch");
+    let mut profile = sub_matches.value_of("profile");
+    if command == "install" && args.arg_source != "" && branch.is_some() {
+        // the checkout is built on whatever branch it is on, so the one argument is the
+        // profile
+        if profile.is_some() || !PROFILES.contains(&branch.unwrap()) {
+            exit_with(Error::with_description(
+                "install --source takes only a profile, debug or release, since the \
+                 checkout is built on the branch it is on",
+                ErrorKind::ArgumentConflict,
+            ));
+        }
+        profile = branch.take();
+    }
+    if let Some(branch) = branch {
+        args.flag_branch = true;
+        args.arg_branch_name = branch.to_string();
+    } else if command == "install" && args.arg_rev != "" && !args.flag_locked {
+        // the version is named after the pinned revision
+        args.flag_branch = true;
+        args.arg_branch_name = args.arg_rev.clone();
+    }
+    match profile {
+        Some(profile) => {
+            args.flag_profile = true;
+            args.compile_type = versions::compile_type(profile);
+        }
+        // update keeps the active version's profile, and a lockfile or bundle has its own
+        None if command == "update" || args.flag_locked || args.arg_from_bundle != "" => {}
+        None => args.compile_type = versions::compile_type("release"),
+    }
+
+    if let (action, Some(action_matches)) = sub_matches.subcommand() {
+        let action_value = |name: &str| {
+            action_matches
+                .value_of(name)
+                .unwrap_or_default()
+                .to_string()
+        };
+        match command.as_ref() {
+            "bundle" => {
+                args.arg_bundle_action = action.to_string();
+                args.arg_bundle_path = action_value("out");
+            }
+            "config" => {
+                args.arg_config_action = action.to_string();
+                args.arg_config_key = action_value("key");
+                if let Some(values) = action_matches.values_of("value") {
+                    args.arg_config_value = values.collect::<Vec<&str>>().join(" ");
+                }
+                args.flag_project = action_matches.is_present("project");
+            }
+            "cache" => {
+                args.arg_cache_action = action.to_string();
+                args.arg_older_than = action_value("older-than");
+            }
+            _ => {}
+        }
+    }
+    (command, args)
+}
@@ -0,0 +1,590 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use error::InstallError;
+use toml::value::{Table, Value};
+
+// Settings are read from, in increasing precedence: the built-in defaults, the user
+// config ~/.scaii/config.toml, a project config sky-install.toml in the current directory
+// and environment variables.  Options given on the command line win over all of them.
+pub const CONFIG_FILENAME: &'static str = "config.toml";
+pub const PROJECT_CONFIG_FILENAME: &'static str = "sky-install.toml";
+pub const SCAII_HOME_VAR: &'static str = "SCAII_HOME";
+
+// every top-level setting, with its default and what it does, for 'config list' and to
+// catch misspelt keys in 'config set'
+const SETTINGS: &'static [(&'static str, &'static str, &'static str)] = &[
+    (
+        "branch",
+        "master",
+        "SCAII branch installed when none is given",
+    ),
+    (
+        "profile",
+        "release",
+        "build profile, debug or release, when none is given",
+    ),
+    (
+        "prefix",
+        "~/.scaii",
+        "where versions are installed, like --prefix",
+    ),
+    (
+        "source",
+        "",
+        "SCAII checkout to build instead of cloning one, like --source",
+    ),
+    ("cargo_features", "[]", "cargo features SCAII is built with"),
+    (
+        "extra_backends",
+        "[]",
+        "other backends to build alongside Sky-RTS",
+    ),
+    ("proxy", "", "proxy for every download and fetch"),
+    ("cache", "true", "keep downloads in the cache"),
+    ("cache_dir", "<prefix>/cache", "where downloads are cached"),
+    (
+        "download_retries",
+        "3",
+        "times a failed download is retried",
+    ),
+    (
+        "download_timeout_secs",
+        "30",
+        "seconds a connection may stall",
+    ),
+    (
+        "protobuf_js_version",
+        "3.5.1",
+        "protobuf release the viz javascript comes from",
+    ),
+    (
+        "protobuf_js_sha256",
+        "",
+        "SHA-256 of that release's archive",
+    ),
+    (
+        "repos",
+        "",
+        "urls and mirrors of SCAII, Sky-RTS and the downloads",
+    ),
+    ("checksums", "", "expected SHA-256 of downloads, by url"),
+];
+
+// the settings that can be given as environment variables
+const ENV_SETTINGS: &'static [(&'static str, &'static str)] = &[
+    ("prefix", SCAII_HOME_VAR),
+    ("branch", "SKY_INSTALL_BRANCH"),
+    ("profile", "SKY_INSTALL_PROFILE"),
+    ("proxy", "SKY_INSTALL_PROXY"),
+    ("cache", "SKY_INSTALL_CACHE"),
+    ("cache_dir", "SKY_INSTALL_CACHE_DIR"),
+    ("download_retries", "SKY_INSTALL_DOWNLOAD_RETRIES"),
+    ("download_timeout_secs", "SKY_INSTALL_DOWNLOAD_TIMEOUT_SECS"),
+];
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Config {
+    // what install builds when the command line doesn't say
+    pub branch: Option<String>,
+    pub profile: Option<String>,
+    // the install root, ~/.scaii unless given
+    pub prefix: Option<String>,
+    // build this SCAII checkout on install instead of cloning one, like --source
+    pub source: Option<String>,
+    // passed to cargo as --features when building SCAII
+    #[serde(default)]
+    pub cargo_features: Vec<String>,
+    #[serde(default)]
+    pub extra_backends: Vec<ExtraBackend>,
+    // used for every download and fetch, including cargo's
+    pub proxy: Option<String>,
+    // whether downloads are kept in the cache, and where it is
+    pub cache: Option<bool>,
+    pub cache_dir: Option<String>,
+    // the protobuf release whose javascript goes into viz/js/protobuf_js, and the
+    // SHA-256 its archive must have
+    pub protobuf_js_version: Option<String>,
+    pub protobuf_js_sha256: Option<String>,
+    // expected SHA-256 digests of downloads, by url, overriding the built-in ones
+    #[serde(default)]
+    pub checksums: BTreeMap<String, String>,
+    // how many times a failed download is retried, and how many seconds a connection
+    // may take to open or sit stalled before it counts as failed
+    pub download_retries: Option<u32>,
+    pub download_timeout_secs: Option<u64>,
+    // where SCAII, Sky-RTS, closure-library and protobuf-js are fetched from, by name
+    #[serde(default)]
+    pub repos: BTreeMap<String, RepoConfig>,
+    // the install root everything else is kept in
+    #[serde(skip)]
+    pub dot_scaii: PathBuf,
+}
+
+// url replaces the default location of a repository or download, and mirrors are tried
+// in order after it when it can't be reached.  Either may be a local path or file:// url,
+// and download urls may contain {version}.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RepoConfig {
+    pub url: Option<String>,
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+}
+
+// a backend cloned into SCAII's backends dir next to Sky-RTS, built with cargo in
+// crate_dir, and whose libraries are installed into backends/bin
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ExtraBackend {
+    pub name: String,
+    pub url: String,
+    pub rev: Option<String>,
+    pub crate_dir: Option<String>,
+}
+
+// one source of settings, named for 'config get' and 'config list'
+struct Layer {
+    name: String,
+    table: Table,
+}
+
+impl Config {
+    // every layer merged together, the user config being the one under home_dir and the
+    // environment being vars, as env_vars() reads it.  Missing files are empty layers.
+    pub fn load(
+        project_dir: &PathBuf,
+        home_dir: Option<PathBuf>,
+        vars: &BTreeMap<String, String>,
+    ) -> Result<Config, Box<Error>> {
+        use platform::common;
+
+        let user_config_dir = user_config_dir(home_dir)?;
+        let layers = load_layers(project_dir, &user_config_dir, vars)?;
+        let mut config = config_from(&merge_layers(&layers))?;
+        config.dot_scaii = match config.prefix {
+            Some(ref prefix) if prefix != "" => PathBuf::from(prefix),
+            _ => user_config_dir,
+        };
+        common::ensure_dir_exists(&config.dot_scaii)?;
+        if let Some(ref profile) = config.profile {
+            if profile != "debug" && profile != "release" {
+                return Err(Box::new(InstallError::Usage(format!(
+                    "profile must be debug or release, not '{}'.",
+                    profile
+                ))));
+            }
+        }
+        Ok(config)
+    }
+
+    // the places to fetch the named repository or download from, in the order to try them
+    pub fn repo_urls(&self, name: &str, default_url: &str) -> Vec<String> {
+        let mut urls = Vec::new();
+        match self.repos.get(name) {
+            Some(repo) => {
+                urls.push(repo.url.clone().unwrap_or(default_url.to_string()));
+                urls.extend(repo.mirrors.iter().cloned());
+            }
+            None => urls.push(default_url.to_string()),
+        }
+        urls
+    }
+
+    // hands the proxy to curl, git and cargo, which all read these variables
+    pub fn apply_proxy(&self) {
+        use std::env;
+
+        if let Some(ref proxy) = self.proxy {
+            for var in ["http_proxy", "https_proxy", "HTTP_PROXY", "HTTPS_PROXY"].iter() {
+                env::set_var(var, proxy);
+            }
+        }
+    }
+}
+
+// ~/.scaii, which holds the user config and is the default install root
+pub fn get_user_config_dir() -> Result<PathBuf, Box<Error>> {
+    use std::env;
+
+    user_config_dir(env::home_dir())
+}
+
+fn user_config_dir(home_dir: Option<PathBuf>) -> Result<PathBuf, Box<Error>> {
+    match home_dir {
+        Some(home_dir) => Ok(home_dir.join(".scaii")),
+        None => Err(Box::new(InstallError::new(
+            "could not determine user's home directory".to_string(),
+        ))),
+    }
+}
+
+// prints the value of a setting and where it came from.  Keys of tables are joined
+// with dots, as in repos.SCAII.url.
+pub fn get(project_dir: &PathBuf, key: &str) -> Result<(), Box<Error>> {
+    let layers = load_layers(project_dir, &get_user_config_dir()?, &env_vars())?;
+    for layer in layers.iter().rev() {
+        if let Some(value) = lookup(&layer.table, key) {
+            println!("{} = {}    ({})", key, value, layer.name);
+            return Ok(());
+        }
+    }
+    match SETTINGS.iter().find(|&&(name, _, _)| name == key) {
+        Some(&(_, default, _)) if default != "" => println!("{} = {}    (default)", key, default),
+        _ => println!("{} is not set", key),
+    }
+    Ok(())
+}
+
+// writes a setting into the user config, or the project config if project is set.  The
+// value is read as TOML - a number, true or false, or a ["list"] - falling back to a
+// plain string.
+pub fn set(project_dir: &PathBuf, key: &str, value: &str, project: bool) -> Result<(), Box<Error>> {
+    use std::io::Write;
+    use toml;
+
+    let top_level = key.split('.').next().unwrap_or(key);
+    if !SETTINGS.iter().any(|&(name, _, _)| name == top_level) {
+        return Err(Box::new(InstallError::Usage(format!(
+            "there is no setting named {} - 'config list' shows them all.",
+            top_level
+        ))));
+    }
+    let path = if project {
+        project_dir.join(PROJECT_CONFIG_FILENAME)
+    } else {
+        get_user_config_dir()?.join(CONFIG_FILENAME)
+    };
+    let mut table = read_table(&path)?;
+    let mut parsed = parse_value(value);
+    insert(&mut table, key, parsed.clone())?;
+    if config_from(&table).is_err() {
+        // e.g. a branch named 1234 is still a branch name
+        parsed = Value::String(value.to_string());
+        insert(&mut table, key, parsed.clone())?;
+        config_from(&table)?;
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = fs::File::create(&path)?;
+    file.write_all(toml::to_string(&Value::Table(table))?.as_bytes())?;
+    println!("set {} = {} in {:?}", key, parsed, path);
+    Ok(())
+}
+
+// prints every setting in effect, with where it came from
+pub fn list(project_dir: &PathBuf) -> Result<(), Box<Error>> {
+    let layers = load_layers(project_dir, &get_user_config_dir()?, &env_vars())?;
+    for layer in layers.iter() {
+        println!("# {}", layer.name);
+    }
+    println!("");
+    for &(name, default, description) in SETTINGS.iter() {
+        println!("# {}", description);
+        let mut found = false;
+        for layer in layers.iter().rev() {
+            if let Some(value) = layer.table.get(name) {
+                let mut lines = Vec::new();
+                flatten(name, value, &mut lines);
+                for line in lines {
+                    println!("{}    ({})", line, layer.name);
+                }
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            if default != "" {
+                println!("{} = {}    (default)", name, default);
+            } else {
+                println!("{} is not set", name);
+            }
+        }
+    }
+    Ok(())
+}
+
+// the layers in increasing precedence
+fn load_layers(
+    project_dir: &PathBuf,
+    user_config_dir: &PathBuf,
+    vars: &BTreeMap<String, String>,
+) -> Result<Vec<Layer>, Box<Error>> {
+    let user_path = user_config_dir.join(CONFIG_FILENAME);
+    let project_path = project_dir.join(PROJECT_CONFIG_FILENAME);
+    Ok(vec![
+        Layer {
+            name: user_path.to_string_lossy().to_string(),
+            table: read_table(&user_path)?,
+        },
+        Layer {
+            name: project_path.to_string_lossy().to_string(),
+            table: read_table(&project_path)?,
+        },
+        Layer {
+            name: "environment".to_string(),
+            table: env_table(vars)?,
+        },
+    ])
+}
+
+fn read_table(path: &PathBuf) -> Result<Table, Box<Error>> {
+    use std::io::Read;
+    use toml;
+
+    if !path.as_path().exists() {
+        return Ok(Table::new());
+    }
+    let mut contents = String::new();
+    fs::File::open(path)?.read_to_string(&mut contents)?;
+    match toml::from_str(&contents) {
+        Ok(table) => Ok(table),
+        Err(err) => Err(Box::new(InstallError::Usage(format!(
+            "could not read {:?}: {}",
+            path, err
+        )))),
+    }
+}
+
+// the process environment, leaving out variables that aren't valid unicode
+pub fn env_vars() -> BTreeMap<String, String> {
+    use std::env;
+
+    env::vars_os()
+        .filter_map(
+            |(var, value)| match (var.into_string(), value.into_string()) {
+                (Ok(var), Ok(value)) => Some((var, value)),
+                _ => None,
+            },
+        )
+        .collect()
+}
+
+fn env_table(vars: &BTreeMap<String, String>) -> Result<Table, Box<Error>> {
+    let mut table = Table::new();
+    for &(name, var) in ENV_SETTINGS.iter() {
+        if let Some(value) = vars.get(var) {
+            if value == "" {
+                continue;
+            }
+            let mut parsed = parse_value(value);
+            let mut single = Table::new();
+            single.insert(name.to_string(), parsed.clone());
+            if config_from(&single).is_err() {
+                parsed = Value::String(value.clone());
+            }
+            table.insert(name.to_string(), parsed);
+        }
+    }
+    Ok(table)
+}
+
+fn merge_layers(layers: &Vec<Layer>) -> Table {
+    let mut merged = Table::new();
+    for layer in layers.iter() {
+        merge(&mut merged, &layer.table);
+    }
+    merged
+}
+
+// copies from into into, merging tables key by key so that, say, a project config can
+// add a mirror for one repo without hiding the user config's other repos
+fn merge(into: &mut Table, from: &Table) {
+    for (key, value) in from.iter() {
+        let merged = match (into.get_mut(key), value) {
+            (Some(&mut Value::Table(ref mut existing)), &Value::Table(ref table)) => {
+                merge(existing, table);
+                true
+            }
+            _ => false,
+        };
+        if !merged {
+            into.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+fn config_from(table: &Table) -> Result<Config, Box<Error>> {
+    match Value::Table(table.clone()).try_into::<Config>() {
+        Ok(config) => Ok(config),
+        Err(err) => Err(Box::new(InstallError::Usage(format!(
+            "invalid setting: {}",
+            err
+        )))),
+    }
+}
+
+fn parse_value(value: &str) -> Value {
+    use toml;
+
+    match toml::from_str::<Table>(&format!("value = {}", value)) {
+        Ok(mut parsed) => parsed
+            .remove("value")
+            .unwrap_or(Value::String(value.to_string())),
+        Err(_) => Value::String(value.to_string()),
+    }
+}
+
+fn lookup<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    let mut parts = key.split('.');
+    let mut value = table.get(parts.next().unwrap_or(key))?;
+    for part in parts {
+        value = value.as_table()?.get(part)?;
+    }
+    Some(value)
+}
+
+fn insert(table: &mut Table, key: &str, value: Value) -> Result<(), Box<Error>> {
+    let parts: Vec<&str> = key.split('.').collect();
+    let (last, parents) = parts.split_last().unwrap();
+    let mut current = table;
+    for part in parents.iter() {
+        let entry = current
+            .entry(part.to_string())
+            .or_insert(Value::Table(Table::new()));
+        current = match *entry {
+            Value::Table(ref mut nested) => nested,
+            _ => {
+                return Err(Box::new(InstallError::Usage(format!(
+                    "{} is not a table of settings.",
+                    part
+                ))))
+            }
+        };
+    }
+    current.insert(last.to_string(), value);
+    Ok(())
+}
+
+// a setting as the lines of 'key = value' it takes, with tables spelt out key by key
+fn flatten(key: &str, value: &Value, lines: &mut Vec<String>) {
+    match *value {
+        Value::Table(ref table) => {
+            for (nested_key, nested) in table.iter() {
+                flatten(&format!("{}.{}", key, nested_key), nested, lines);
+            }
+        }
+        _ => lines.push(format!("{} = {}", key, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::Write;
+
+    fn write_file(path: &PathBuf, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::File::create(path)
+            .unwrap()
+            .write_all(contents.as_bytes())
+            .unwrap();
+    }
+
+    #[test]
+    fn later_layers_win_and_tables_merge_key_by_key() {
+        let user: Table = ::toml::from_str(
+            "branch = \"user\"
+profile = \"debug\"
+[repos.SCAII]
+url = \"https://user/SCAII\"
+[repos.Sky-RTS]
+url = \"https://user/Sky-RTS\"",
+        )
+        .unwrap();
+        let project: Table = ::toml::from_str(
+            "branch = \"project\"
+[repos.SCAII]
+mirrors = [\"file:///mirror/SCAII\"]",
+        )
+        .unwrap();
+        let layers = vec![
+            Layer {
+                name: "user".to_string(),
+                table: user,
+            },
+            Layer {
+                name: "project".to_string(),
+                table: project,
+            },
+        ];
+        let config = config_from(&merge_layers(&layers)).unwrap();
+        assert_eq!(config.branch, Some("project".to_string()));
+        assert_eq!(config.profile, Some("debug".to_string()));
+        assert_eq!(
+            config.repo_urls("SCAII", "https://default/SCAII"),
+            vec!["https://user/SCAII", "file:///mirror/SCAII"]
+        );
+        assert_eq!(
+            config.repo_urls("Sky-RTS", "https://default/Sky-RTS"),
+            vec!["https://user/Sky-RTS"]
+        );
+        assert_eq!(
+            config.repo_urls("closure-library", "https://default/closure"),
+            vec!["https://default/closure"]
+        );
+    }
+
+    #[test]
+    fn invalid_settings_are_usage_errors() {
+        let table: Table = ::toml::from_str("download_retries = \"many\"").unwrap();
+        match config_from(&table) {
+            Err(err) => match err.downcast_ref::<InstallError>() {
+                Some(&InstallError::Usage(_)) => {}
+                _ => panic!("expected a usage error, got {}", err),
+            },
+            Ok(_) => panic!("accepted a download_retries that isn't a number"),
+        }
+    }
+
+    #[test]
+    fn load_reads_user_then_project_config_then_environment() {
+        let dir = env::temp_dir().join(format!("sky-install-{}-config", ::std::process::id()));
+        if dir.as_path().exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        let home = dir.join("home");
+        let project_dir = dir.join("project");
+        let prefix = dir.join("prefix");
+        write_file(
+            &home.join(".scaii").join(CONFIG_FILENAME),
+            &format!(
+                "branch = \"user\"\nprofile = \"debug\"\ndownload_retries = 5\nprefix = {:?}\n",
+                prefix.to_string_lossy()
+            ),
+        );
+        write_file(
+            &project_dir.join(PROJECT_CONFIG_FILENAME),
+            "branch = \"project\"\ndownload_retries = 7\n",
+        );
+        let mut vars = BTreeMap::new();
+        vars.insert("SKY_INSTALL_DOWNLOAD_RETRIES".to_string(), "9".to_string());
+        vars.insert("SKY_INSTALL_PROFILE".to_string(), "".to_string());
+
+        let config = Config::load(&project_dir, Some(home.clone()), &vars).unwrap();
+        assert_eq!(config.branch, Some("project".to_string()));
+        assert_eq!(config.profile, Some("debug".to_string()));
+        assert_eq!(config.download_retries, Some(9));
+        assert_eq!(config.dot_scaii, prefix);
+        assert!(prefix.as_path().exists());
+
+        vars.insert("SKY_INSTALL_PROFILE".to_string(), "fast".to_string());
+        assert!(Config::load(&project_dir, Some(home.clone()), &vars).is_err());
+
+        // without a prefix anywhere, everything is installed alongside the user config
+        let scaii_home = dir.join("scaii-home");
+        vars.remove("SKY_INSTALL_PROFILE");
+        vars.insert(
+            SCAII_HOME_VAR.to_string(),
+            scaii_home.to_string_lossy().to_string(),
+        );
+        let config = Config::load(&project_dir, Some(home.clone()), &vars).unwrap();
+        assert_eq!(config.dot_scaii, scaii_home);
+        let config = Config::load(&dir.join("elsewhere"), Some(dir.clone()), &BTreeMap::new());
+        assert_eq!(config.unwrap().dot_scaii, dir.join(".scaii"));
+        assert!(Config::load(&project_dir, None, &BTreeMap::new()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
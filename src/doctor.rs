@@ -0,0 +1,407 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use config::Config;
+use error::InstallError;
+
+// 'doctor' checks for everything an install and SCAII need, and install runs the checks
+// it depends on first, so that a missing program is reported with how to get it rather
+// than as a cargo or git error part way through.  Python, its protobuf package and protoc
+// are only needed to run SCAII, so their absence is a warning rather than a failure.
+const MIN_RUST_VERSION: [u32; 3] = [1, 30, 0];
+// cargo is versioned apart from rustc, and a patch release of Rust can ship an earlier
+// cargo, as Rust 1.26.2 did with cargo 1.26.0
+const MIN_CARGO_VERSION: [u32; 3] = [1, 30, 0];
+// free space needed in the install root to build SCAII and Sky-RTS, or to unpack a
+// prebuilt version
+const MIN_FREE_MB_BUILD: u64 = 2048;
+const MIN_FREE_MB_PREBUILT: u64 = 256;
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
+
+enum Status {
+    Passed(String),
+    Warning(String),
+    Failed(String),
+}
+
+struct Check {
+    name: &'static str,
+    status: Status,
+    // how to fix it, shown when it doesn't pass
+    remedy: String,
+}
+
+impl Check {
+    // a check whose failure is only a warning
+    fn optional(mut self) -> Check {
+        if let Status::Failed(problem) = self.status {
+            self.status = Status::Warning(problem);
+        }
+        self
+    }
+
+    fn print(&self, show_passed: bool) {
+        match self.status {
+            Status::Passed(ref details) => {
+                if show_passed {
+                    println!("  ok    {:<10} {}", self.name, details)
+                }
+            }
+            Status::Warning(ref problem) => {
+                println!("  warn  {:<10} {}", self.name, problem);
+                println!("        {:<10} {}", "", self.remedy);
+            }
+            Status::Failed(ref problem) => {
+                println!("  FAIL  {:<10} {}", self.name, problem);
+                println!("        {:<10} {}", "", self.remedy);
+            }
+        }
+    }
+
+    fn failed(&self) -> bool {
+        match self.status {
+            Status::Failed(_) => true,
+            _ => false,
+        }
+    }
+}
+
+// runs every check, printing each outcome, and fails if any required check did
+pub fn run(config: &Config) -> Result<(), Box<Error>> {
+    println!("checking what SCAII needs...");
+    let checks = run_checks(config, true, true);
+    for check in checks.iter() {
+        check.print(true);
+    }
+    finish(&checks, "doctor")
+}
+
+// runs the checks an install depends on, printing only the ones that didn't pass.  A
+// prebuilt install doesn't compile or clone anything, and a bundle doesn't use the
+// network.
+pub fn check_before_install(
+    config: &Config,
+    needs_build: bool,
+    needs_network: bool,
+) -> Result<(), Box<Error>> {
+    let checks = run_checks(config, needs_build, needs_network);
+    for check in checks.iter() {
+        check.print(false);
+    }
+    finish(&checks, "install")
+}
+
+fn finish(checks: &Vec<Check>, command: &str) -> Result<(), Box<Error>> {
+    let failed = checks.iter().filter(|check| check.failed()).count();
+    if failed == 0 {
+        return Ok(());
+    }
+    Err(Box::new(InstallError::Prerequisite(format!(
+        "{} of the checks {} needs failed - fix what is listed above, then run \
+         'sky-install doctor' to check again.",
+        failed, command
+    ))))
+}
+
+fn run_checks(config: &Config, needs_build: bool, needs_network: bool) -> Vec<Check> {
+    let mut checks = Vec::new();
+    if needs_build {
+        checks.push(check_rust("rustc", &MIN_RUST_VERSION));
+        checks.push(check_rust("cargo", &MIN_CARGO_VERSION));
+        checks.push(check_build_tools());
+        checks.push(check_git());
+    }
+    checks.push(check_python().optional());
+    checks.push(check_python_protobuf().optional());
+    checks.push(check_protoc().optional());
+    if needs_network {
+        checks.push(check_network(config));
+    }
+    checks.push(check_disk_space(config, needs_build));
+    checks
+}
+
+fn check_rust(command: &'static str, minimum: &[u32]) -> Check {
+    let remedy = format!(
+        "install Rust from https://www.rust-lang.org/en-US/install.html, or run \
+         'rustup update' - {} {} or later is needed",
+        command,
+        version_string(minimum)
+    );
+    let status = match first_line_of(command, &["--version"]) {
+        Ok(line) => match meets_minimum(&line, minimum) {
+            Some(true) => Status::Passed(line),
+            Some(false) => Status::Failed(format!("{} is too old", line)),
+            None => Status::Failed(format!("can't tell the version from '{}'", line)),
+        },
+        Err(problem) => Status::Failed(problem),
+    };
+    Check {
+        name: command,
+        status: status,
+        remedy: remedy,
+    }
+}
+
+// the linker cargo needs: a C compiler on linux and macos, the Visual Studio build tools
+// on windows
+fn check_build_tools() -> Check {
+    use platform;
+
+    let remedy = if cfg!(windows) {
+        "install the Visual Studio Build Tools with the C++ tools from \
+         https://www.visualstudio.com/downloads/#build-tools-for-visual-studio-2017"
+    } else if cfg!(target_os = "macos") {
+        "install the Xcode command line tools with 'xcode-select --install'"
+    } else {
+        "install a C compiler, e.g. 'sudo apt-get install build-essential'"
+    };
+    Check {
+        name: "linker",
+        status: match platform::find_build_tools() {
+            Ok(found) => Status::Passed(found),
+            Err(err) => Status::Failed(err.to_string()),
+        },
+        remedy: remedy.to_string(),
+    }
+}
+
+fn check_git() -> Check {
+    Check {
+        name: "git",
+        status: match first_line_of("git", &["--version"]) {
+            Ok(line) => Status::Passed(line),
+            Err(problem) => Status::Failed(problem),
+        },
+        remedy: "install git from https://git-scm.com/downloads".to_string(),
+    }
+}
+
+fn check_python() -> Check {
+    let remedy = "install Python 3 from https://www.python.org/downloads/".to_string();
+    let status = match first_line_of(python_command(), &["--version"]) {
+        Ok(ref line) if line.starts_with("Python 3") => Status::Passed(line.clone()),
+        Ok(line) => Status::Failed(format!("{} is not Python 3", line)),
+        Err(problem) => Status::Failed(problem),
+    };
+    Check {
+        name: "python",
+        status: status,
+        remedy: remedy,
+    }
+}
+
+fn check_python_protobuf() -> Check {
+    let script = "import google.protobuf; print(google.protobuf.__version__)";
+    Check {
+        name: "protobuf",
+        status: match first_line_of(python_command(), &["-c", script]) {
+            Ok(version) => Status::Passed(format!("python protobuf {}", version)),
+            Err(_) => Status::Failed(format!(
+                "the protobuf package can't be imported by {}",
+                python_command()
+            )),
+        },
+        remedy: "run 'pip3 install protobuf'".to_string(),
+    }
+}
+
+fn check_protoc() -> Check {
+    Check {
+        name: "protoc",
+        status: match first_line_of("protoc", &["--version"]) {
+            Ok(line) => Status::Passed(line),
+            Err(problem) => Status::Failed(problem),
+        },
+        remedy: "install protoc from https://github.com/google/protobuf/releases and put it \
+                 on the PATH"
+            .to_string(),
+    }
+}
+
+// passes if any of the places SCAII is fetched from answers.  Local repositories and
+// mirrors need no network, so they aren't checked.
+fn check_network(config: &Config) -> Check {
+    use lockfile::ARTIFACT_SCAII;
+    use platform::common::SCAII_URL;
+
+    let remedy = "check the network connection, set the proxy setting if there is a proxy, \
+                  or list reachable mirrors in [repos]"
+        .to_string();
+    let urls: Vec<String> = config
+        .repo_urls(ARTIFACT_SCAII, SCAII_URL)
+        .into_iter()
+        .filter(|url| url.starts_with("http://") || url.starts_with("https://"))
+        .collect();
+    if urls.is_empty() {
+        return Check {
+            name: "network",
+            status: Status::Passed("SCAII is fetched from a local repository".to_string()),
+            remedy: remedy,
+        };
+    }
+    let timeout = config.download_timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS);
+    let mut problems = Vec::new();
+    for url in urls.iter() {
+        match reach(url, timeout) {
+            Ok(()) => {
+                return Check {
+                    name: "network",
+                    status: Status::Passed(format!("reached {}", url)),
+                    remedy: remedy,
+                }
+            }
+            Err(err) => problems.push(format!("{} ({})", url, err)),
+        }
+    }
+    Check {
+        name: "network",
+        status: Status::Failed(format!("could not reach {}", problems.join(", "))),
+        remedy: remedy,
+    }
+}
+
+// asks url for its headers, which is enough to know it can be downloaded from
+fn reach(url: &str, timeout_secs: u64) -> Result<(), Box<Error>> {
+    use curl::easy::Easy;
+    use std::time::Duration;
+
+    let mut easy = Easy::new();
+    easy.url(url)?;
+    easy.nobody(true)?;
+    easy.follow_location(true)?;
+    easy.connect_timeout(Duration::from_secs(timeout_secs))?;
+    easy.timeout(Duration::from_secs(timeout_secs * 2))?;
+    easy.perform()?;
+    Ok(())
+}
+
+fn check_disk_space(config: &Config, needs_build: bool) -> Check {
+    use platform;
+
+    let needed = if needs_build {
+        MIN_FREE_MB_BUILD
+    } else {
+        MIN_FREE_MB_PREBUILT
+    };
+    // the install root may not exist yet, so measure the filesystem it will be made on
+    let mut dir: PathBuf = config.dot_scaii.clone();
+    while !dir.as_path().exists() && dir.pop() {}
+    let status = match platform::free_space_mb(&dir) {
+        Ok(free) if free >= needed => Status::Passed(format!("{} MiB free in {:?}", free, dir)),
+        Ok(free) => Status::Failed(format!(
+            "only {} MiB free in {:?}, {} MiB are needed",
+            free, dir, needed
+        )),
+        Err(err) => Status::Warning(format!(
+            "could not tell the free space in {:?}: {}",
+            dir, err
+        )),
+    };
+    Check {
+        name: "disk space",
+        status: status,
+        remedy: "free up some space, or install somewhere else with --prefix".to_string(),
+    }
+}
+
+// python 3 is python3 except on windows, where the installer names it python
+fn python_command() -> &'static str {
+    if cfg!(windows) {
+        "python"
+    } else {
+        "python3"
+    }
+}
+
+// the first line command prints, on stdout or on stderr as older pythons do, or what went
+// wrong running it
+fn first_line_of(command: &str, args: &[&str]) -> Result<String, String> {
+    use std::process::Command;
+
+    let output = match Command::new(command).args(args).output() {
+        Ok(output) => output,
+        Err(_) => return Err(format!("{} was not found", command)),
+    };
+    if !output.status.success() {
+        return Err(format!(
+            "{} {} failed: {}",
+            command,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    let text = if stdout.trim() != "" { stdout } else { stderr };
+    Ok(text.lines().next().unwrap_or("").trim().to_string())
+}
+
+// whether the version in a --version line, e.g. "cargo 1.30.0 (36d96825d 2018-10-24)",
+// is at least minimum, or None if it has no version
+fn meets_minimum(line: &str, minimum: &[u32]) -> Option<bool> {
+    match parse_version(line.split_whitespace().nth(1).unwrap_or("")) {
+        Some(version) => Some(version[..] >= *minimum),
+        None => None,
+    }
+}
+
+// "1.26.2" or "1.28.0-nightly" as [1, 26, 2] and [1, 28, 0]
+fn parse_version(version: &str) -> Option<Vec<u32>> {
+    let numbers: Vec<Option<u32>> = version
+        .split(|c| c == '.' || c == '-')
+        .take(3)
+        .map(|part| part.parse::<u32>().ok())
+        .collect();
+    if numbers.len() < 3 || numbers.iter().any(|number| number.is_none()) {
+        return None;
+    }
+    Some(numbers.into_iter().map(|number| number.unwrap()).collect())
+}
+
+fn version_string(version: &[u32]) -> String {
+    let parts: Vec<String> = version.iter().map(|part| part.to_string()).collect();
+    parts.join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_release_and_nightly_versions() {
+        assert_eq!(parse_version("1.26.2"), Some(vec![1, 26, 2]));
+        assert_eq!(parse_version("1.28.0-nightly"), Some(vec![1, 28, 0]));
+        assert_eq!(parse_version("2.17.1.windows.2"), Some(vec![2, 17, 1]));
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_version() {
+        assert_eq!(parse_version(""), None);
+        assert_eq!(parse_version("1.26"), None);
+        assert_eq!(parse_version("1.x.2"), None);
+        assert_eq!(parse_version("rustc"), None);
+    }
+
+    #[test]
+    fn compares_versions_numerically() {
+        let minimum = &MIN_RUST_VERSION[..];
+        assert!(parse_version("1.30.0").unwrap()[..] >= *minimum);
+        assert!(parse_version("1.100.0").unwrap()[..] >= *minimum);
+        assert!(parse_version("1.26.2").unwrap()[..] < *minimum);
+        assert!(parse_version("1.9.9").unwrap()[..] < *minimum);
+        assert_eq!(version_string(&MIN_RUST_VERSION), "1.30.0");
+    }
+
+    #[test]
+    fn checks_rustc_and_cargo_against_their_own_minimums() {
+        let cargo = "cargo 1.30.0 (36d96825d 2018-10-24)";
+        assert_eq!(meets_minimum(cargo, &MIN_CARGO_VERSION), Some(true));
+        assert_eq!(meets_minimum("rustc 1.30.1", &MIN_RUST_VERSION), Some(true));
+        assert_eq!(
+            meets_minimum("cargo 1.29.0", &MIN_CARGO_VERSION),
+            Some(false)
+        );
+        assert_eq!(meets_minimum("rustc", &MIN_RUST_VERSION), None);
+    }
+}
@@ -1,26 +1,277 @@
 use std::error::Error;
 use std::fmt;
 use std::fmt::Display;
+use std::path::PathBuf;
 
+// What went wrong, which decides the installer's exit code so that scripts can react to
+// it.  The codes are listed in the README and must not change.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    // anything not covered below
+    Other,
+    // the command line, a setting or a lockfile is wrong
+    Usage,
+    // a download or fetch failed, or what was downloaded isn't what was expected
+    Network,
+    // a git operation on a checkout failed
+    Git,
+    // cargo couldn't build SCAII, Sky-RTS or a backend
+    Build,
+    // reading or writing files failed
+    Filesystem,
+    // a program, install or file the command needs is missing
+    Prerequisite,
+}
+
+impl ErrorKind {
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            ErrorKind::Other => 1,
+            ErrorKind::Usage => 2,
+            ErrorKind::Network => 3,
+            ErrorKind::Git => 4,
+            ErrorKind::Build => 5,
+            ErrorKind::Filesystem => 6,
+            ErrorKind::Prerequisite => 7,
+        }
+    }
+}
+
+// The installer's own failures, one variant per thing that can go wrong.  Those that come
+// from a lower-level error keep it as their cause, so report() can show the whole chain,
+// along with the url, repository, command or path that was being worked on.
 #[derive(Debug)]
-pub struct InstallError {
-    details: String,
+pub enum InstallError {
+    // the command line, a setting or a lockfile is wrong
+    Usage(String),
+    // a program, install or file the command needs is missing
+    Prerequisite(String),
+    // downloading or fetching from url failed
+    Network {
+        url: String,
+        details: String,
+        cause: Option<Box<Error>>,
+    },
+    // a download whose contents don't have the SHA-256 it was expected to, meaning it was
+    // corrupted in transit or isn't what was published
+    Checksum {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+    // the archive at path is damaged, or has an entry that would be extracted outside the
+    // directory it is extracted into
+    Archive {
+        path: PathBuf,
+        details: String,
+        cause: Option<Box<Error>>,
+    },
+    // a git operation on the repository at repo, a path or url, failed
+    Git {
+        repo: String,
+        details: String,
+        cause: Option<Box<Error>>,
+    },
+    // a program the installer ran failed
+    Command {
+        command: String,
+        details: String,
+        cause: Option<Box<Error>>,
+    },
+    // building SCAII, Sky-RTS or a backend in dir failed
+    Build {
+        dir: PathBuf,
+        details: String,
+        cause: Option<Box<Error>>,
+    },
+    // reading, writing, copying or removing path failed
+    Filesystem {
+        path: PathBuf,
+        details: String,
+        cause: Option<Box<Error>>,
+    },
+    Other(String),
 }
 
 impl InstallError {
     pub fn new(msg: String) -> InstallError {
-        InstallError { details: msg }
+        InstallError::Other(msg)
+    }
+
+    pub fn network(url: &str, details: String) -> InstallError {
+        InstallError::Network {
+            url: url.to_string(),
+            details: details,
+            cause: None,
+        }
+    }
+
+    pub fn archive(path: &PathBuf, details: String) -> InstallError {
+        InstallError::Archive {
+            path: path.clone(),
+            details: details,
+            cause: None,
+        }
+    }
+
+    pub fn git(repo: &str, details: String) -> InstallError {
+        InstallError::Git {
+            repo: repo.to_string(),
+            details: details,
+            cause: None,
+        }
+    }
+
+    pub fn command(command: &str, details: String) -> InstallError {
+        InstallError::Command {
+            command: command.to_string(),
+            details: details,
+            cause: None,
+        }
+    }
+
+    pub fn build(dir: &PathBuf, details: String) -> InstallError {
+        InstallError::Build {
+            dir: dir.clone(),
+            details: details,
+            cause: None,
+        }
+    }
+
+    pub fn filesystem(path: &PathBuf, details: String) -> InstallError {
+        InstallError::Filesystem {
+            path: path.clone(),
+            details: details,
+            cause: None,
+        }
+    }
+
+    // records the error that led to this one.  Variants without a cause are returned as
+    // they are.
+    pub fn caused_by(mut self, err: Box<Error>) -> InstallError {
+        match self {
+            InstallError::Network { ref mut cause, .. }
+            | InstallError::Archive { ref mut cause, .. }
+            | InstallError::Git { ref mut cause, .. }
+            | InstallError::Command { ref mut cause, .. }
+            | InstallError::Build { ref mut cause, .. }
+            | InstallError::Filesystem { ref mut cause, .. } => *cause = Some(err),
+            _ => {}
+        }
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            InstallError::Usage(_) => ErrorKind::Usage,
+            InstallError::Prerequisite(_) => ErrorKind::Prerequisite,
+            InstallError::Network { .. }
+            | InstallError::Checksum { .. }
+            | InstallError::Archive { .. } => ErrorKind::Network,
+            InstallError::Git { .. } => ErrorKind::Git,
+            InstallError::Build { .. } => ErrorKind::Build,
+            InstallError::Filesystem { .. } => ErrorKind::Filesystem,
+            InstallError::Command { .. } | InstallError::Other(_) => ErrorKind::Other,
+        }
     }
 }
 
 impl Display for InstallError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.details)
+        match *self {
+            InstallError::Usage(ref details)
+            | InstallError::Prerequisite(ref details)
+            | InstallError::Other(ref details) => write!(f, "{}", details),
+            InstallError::Network {
+                ref url,
+                ref details,
+                ..
+            } => write!(f, "{} ({})", details, url),
+            InstallError::Checksum {
+                ref url,
+                ref expected,
+                ref actual,
+            } => write!(
+                f,
+                "checksum mismatch for {}: expected SHA-256 {} but the download has {}",
+                url, expected, actual
+            ),
+            InstallError::Archive {
+                ref path,
+                ref details,
+                ..
+            } => write!(f, "{} (in {:?})", details, path),
+            InstallError::Git {
+                ref repo,
+                ref details,
+                ..
+            } => write!(f, "{} ({})", details, repo),
+            InstallError::Command {
+                ref command,
+                ref details,
+                ..
+            } => write!(f, "{} failed: {}", command, details),
+            InstallError::Build {
+                ref dir,
+                ref details,
+                ..
+            } => write!(f, "{} (in {:?})", details, dir),
+            InstallError::Filesystem {
+                ref path,
+                ref details,
+                ..
+            } => write!(f, "{} ({:?})", details, path),
+        }
     }
 }
 
 impl Error for InstallError {
-    fn description(&self) -> &str {
-        &self.details
+    fn source(&self) -> Option<&(Error + 'static)> {
+        match *self {
+            InstallError::Network { ref cause, .. }
+            | InstallError::Archive { ref cause, .. }
+            | InstallError::Git { ref cause, .. }
+            | InstallError::Command { ref cause, .. }
+            | InstallError::Build { ref cause, .. }
+            | InstallError::Filesystem { ref cause, .. } => match *cause {
+                Some(ref cause) => Some(&**cause),
+                None => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+// the kind of a failure, from the variant of an InstallError, else from the kind of
+// error it is
+pub fn error_kind(err: &(Error + 'static)) -> ErrorKind {
+    use curl;
+    use fs_extra;
+    use std::io;
+    use toml;
+
+    if let Some(err) = err.downcast_ref::<InstallError>() {
+        return err.kind();
+    }
+    if err.is::<curl::Error>() {
+        return ErrorKind::Network;
+    }
+    if err.is::<io::Error>() || err.is::<fs_extra::error::Error>() {
+        return ErrorKind::Filesystem;
+    }
+    if err.is::<toml::de::Error>() {
+        return ErrorKind::Usage;
+    }
+    ErrorKind::Other
+}
+
+// err followed by each error that led to it, one per line
+pub fn report(err: &(Error + 'static)) -> String {
+    let mut report = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        report.push_str(&format!("\n    caused by: {}", cause));
+        source = cause.source();
     }
+    report
 }
@@ -0,0 +1,203 @@
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use error::InstallError;
+
+// sky-install.lock pins everything an install fetches - the SCAII and Sky-RTS commits and
+// the exact closure library and protobuf javascript it pulled in - so that install
+// --locked reproduces the same environment or refuses to run.  It is written to the
+// current directory by 'lock' and read from there by 'install --locked'.
+pub const LOCKFILE_NAME: &'static str = "sky-install.lock";
+
+pub const ARTIFACT_SCAII: &'static str = "SCAII";
+pub const ARTIFACT_SKY_RTS: &'static str = "Sky-RTS";
+pub const ARTIFACT_CLOSURE_LIBRARY: &'static str = "closure-library";
+pub const ARTIFACT_PROTOBUF_JS: &'static str = "protobuf-js";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub branch: String,
+    pub profile: String,
+    #[serde(default, rename = "artifact")]
+    pub artifacts: Vec<Artifact>,
+}
+
+// something fetched from the network: a repository, identified by commit, or a
+// download, identified by the SHA-256 of what was downloaded
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Artifact {
+    pub name: String,
+    #[serde(default)]
+    pub version: Option<String>,
+    pub url: String,
+    #[serde(default)]
+    pub commit: Option<String>,
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+impl Lockfile {
+    pub fn load(path: &PathBuf) -> Result<Lockfile, Box<Error>> {
+        use std::io::Read;
+        use toml;
+
+        if !path.as_path().exists() {
+            return Err(Box::new(InstallError::Prerequisite(format!(
+                "{:?} not found - run 'lock' to create it from the active version.",
+                path
+            ))));
+        }
+        let mut contents = String::new();
+        fs::File::open(path)?.read_to_string(&mut contents)?;
+        let lockfile: Lockfile = toml::from_str(&contents)?;
+        Ok(lockfile)
+    }
+
+    pub fn save(&self, path: &PathBuf) -> Result<(), Box<Error>> {
+        use std::io::Write;
+        use toml;
+
+        let contents = toml::to_string(self)?;
+        let mut file = fs::File::create(path)?;
+        file.write_all(contents.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn artifact(&self, name: &str) -> Result<&Artifact, Box<Error>> {
+        match self.artifacts.iter().find(|artifact| artifact.name == name) {
+            Some(artifact) => Ok(artifact),
+            None => Err(Box::new(InstallError::Usage(format!(
+                "{} has no entry for {} - run 'lock' to regenerate it.",
+                LOCKFILE_NAME, name
+            )))),
+        }
+    }
+}
+
+// the entry for name when installing from a lockfile, None otherwise
+pub fn locked_artifact<'a>(
+    lock: Option<&'a Lockfile>,
+    name: &str,
+) -> Result<Option<&'a Artifact>, Box<Error>> {
+    match lock {
+        Some(lock) => Ok(Some(lock.artifact(name)?)),
+        None => Ok(None),
+    }
+}
+
+impl Artifact {
+    pub fn new(name: &str, url: &str) -> Artifact {
+        Artifact {
+            name: name.to_string(),
+            version: None,
+            url: url.to_string(),
+            commit: None,
+            sha256: None,
+        }
+    }
+
+    // refuses to go on when what was fetched isn't what the lockfile pinned.  A pinned
+    // commit or SHA-256 identifies it wherever it was fetched from, so a mirror or a fork
+    // configured in config.toml still matches.
+    pub fn verify(&self, fetched: &Artifact) -> Result<(), Box<Error>> {
+        let pinned = self.commit.is_some() || self.sha256.is_some();
+        let mismatch = if !pinned && self.url != fetched.url {
+            Some(format!("url {} but fetched {}", self.url, fetched.url))
+        } else if self.commit.is_some() && self.commit != fetched.commit {
+            Some(format!(
+                "commit {} but fetched {}",
+                self.commit.clone().unwrap_or_default(),
+                fetched.commit.clone().unwrap_or_default()
+            ))
+        } else if self.sha256.is_some() && self.sha256 != fetched.sha256 {
+            Some(format!(
+                "sha256 {} but fetched {}",
+                self.sha256.clone().unwrap_or_default(),
+                fetched.sha256.clone().unwrap_or_default()
+            ))
+        } else {
+            None
+        };
+        match mismatch {
+            Some(mismatch) => Err(Box::new(InstallError::network(
+                &fetched.url,
+                format!(
+                    "ERROR - {} does not match {}: locked {}",
+                    self.name, LOCKFILE_NAME, mismatch
+                ),
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn artifact(url: &str, commit: Option<&str>, sha256: Option<&str>) -> Artifact {
+        let mut artifact = Artifact::new(ARTIFACT_SCAII, url);
+        artifact.commit = commit.map(|commit| commit.to_string());
+        artifact.sha256 = sha256.map(|sha256| sha256.to_string());
+        artifact
+    }
+
+    #[test]
+    fn pinned_commit_matches_from_any_url() {
+        let locked = artifact("https://github.com/SCAII/SCAII.git", Some("abc"), None);
+        assert!(locked
+            .verify(&artifact("file:///mirror/SCAII", Some("abc"), None))
+            .is_ok());
+        assert!(locked
+            .verify(&artifact(
+                "https://github.com/SCAII/SCAII.git",
+                Some("def"),
+                None
+            ))
+            .is_err());
+        assert!(locked
+            .verify(&artifact("https://github.com/SCAII/SCAII.git", None, None))
+            .is_err());
+    }
+
+    #[test]
+    fn pinned_sha256_must_match() {
+        let locked = artifact("https://example.com/a.zip", None, Some("0123"));
+        assert!(locked
+            .verify(&artifact("https://mirror/a.zip", None, Some("0123")))
+            .is_ok());
+        assert!(locked
+            .verify(&artifact("https://example.com/a.zip", None, Some("4567")))
+            .is_err());
+    }
+
+    #[test]
+    fn unpinned_artifact_must_come_from_the_same_url() {
+        let locked = artifact("https://example.com/a.zip", None, None);
+        assert!(locked
+            .verify(&artifact("https://example.com/a.zip", Some("abc"), None))
+            .is_ok());
+        assert!(locked
+            .verify(&artifact("https://elsewhere.com/a.zip", None, None))
+            .is_err());
+    }
+
+    #[test]
+    fn missing_artifact_is_a_usage_error() {
+        let lock = Lockfile {
+            branch: "master".to_string(),
+            profile: "release".to_string(),
+            artifacts: vec![artifact("https://github.com/SCAII/SCAII.git", None, None)],
+        };
+        assert!(lock.artifact(ARTIFACT_SCAII).is_ok());
+        match lock.artifact(ARTIFACT_SKY_RTS) {
+            Err(err) => match err.downcast_ref::<InstallError>() {
+                Some(&InstallError::Usage(_)) => {}
+                _ => panic!("expected a usage error, got {}", err),
+            },
+            Ok(_) => panic!("found an artifact the lockfile doesn't have"),
+        }
+        assert!(locked_artifact(None, ARTIFACT_SKY_RTS).unwrap().is_none());
+    }
+}
@@ -1,5 +1,14 @@
+extern crate clap;
 extern crate curl;
+extern crate flate2;
 extern crate fs_extra;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate sha2;
+extern crate tar;
+extern crate toml;
+extern crate xz2;
 extern crate zip;
 
 use std::env;
@@ -7,168 +16,1037 @@ use std::error::Error;
 use std::fs;
 use std::path::PathBuf;
 
+pub(crate) mod bundle;
+
+pub(crate) mod cache;
+
+pub(crate) mod checksums;
+
+pub(crate) mod cli;
+
+pub(crate) mod config;
+
+pub(crate) mod doctor;
+
 pub(crate) mod error;
 
+pub(crate) mod lockfile;
+
+pub(crate) mod manifest;
+
+pub(crate) mod prebuilt;
+
+pub(crate) mod staging;
+
+pub(crate) mod status;
+
+pub(crate) mod versions;
+
 pub(crate) mod platform;
 
+use config::{Config, SCAII_HOME_VAR};
 use fs_extra::dir::CopyOptions;
+use lockfile::{Artifact, Lockfile, LOCKFILE_NAME};
+use manifest::Manifest;
 use platform::*;
+use versions::VersionInfo;
 
-//  install into .scaii/git by default
-//  ___enhancement - user can override location by specifing --here , if build commands don't find under scaii git, then look "here" by default
+//  install into .scaii/versions/<branch>-<profile>/git by default
+//  the .scaii root can be moved with --prefix <dir> or the SCAII_HOME environment variable
 // ___how check for failure of git call on windows?
 
 pub struct Args {
     flag_branch: bool,
     arg_branch_name: String,
+    flag_profile: bool,
+    arg_version_name: String,
+    arg_source: String,
+    arg_rev: String,
+    arg_sky_rts_rev: String,
+    flag_locked: bool,
+    flag_prebuilt: bool,
+    arg_cache_action: String,
+    arg_older_than: String,
+    arg_from_bundle: String,
+    arg_bundle_action: String,
+    arg_bundle_path: String,
+    arg_config_action: String,
+    arg_config_key: String,
+    arg_config_value: String,
+    flag_project: bool,
     compile_type: String,
 }
 
 fn main() {
-    let arguments: Vec<String> = env::args().collect();
-    let args = parse_args(&arguments);
-    let command = arguments[1].clone();
+    let matches = match cli::app().get_matches_safe() {
+        Ok(matches) => matches,
+        Err(err) => cli::exit_with(err),
+    };
+    set_install_root(cli::prefix(&matches));
+    let (command, args) = cli::parse_args(&matches);
     let result = try_command(&command, args);
     match result {
         Ok(()) => {}
         Err(err) => {
-            println!("ERROR running command {} : {}", &command, err.description());
-            usage();
+            println!(
+                "ERROR running command {} : {}",
+                &command,
+                error::report(&*err)
+            );
+            std::process::exit(error::error_kind(&*err).exit_code());
         }
     }
 }
 
-fn usage() {
-    println!(
-        "
-    Sky-Install.
-    
-    Usage:
-        sky-install install <branch name> [debug|release]
-        sky-install reinstall [debug|release]
-        sky-install uninstall
-    
-    Options:
-        install         Performs a clean installation of
-                        Sky-RTS and compiles release version.
-                        Defaults to dev branch if not
-                        specified.
-        reinstall       Quickly recompiles and reinstalls
-                        Sky-RTS without fetching the latest
-                        version from Github.
-        uninstall       Uninstalls Sky-Rts.
-
-    "
-    );
+// --prefix wins over an existing SCAII_HOME.  Either way the root is made absolute and
+// stored in SCAII_HOME, where Config::load picks it up as the prefix setting.
+fn set_install_root(prefix: Option<String>) {
+    let root = match prefix {
+        Some(prefix) => prefix,
+        None => match env::var(SCAII_HOME_VAR) {
+            Ok(ref scaii_home) if scaii_home != "" => scaii_home.clone(),
+            _ => return,
+        },
+    };
+    let mut root_pathbuf = PathBuf::from(root);
+    if root_pathbuf.is_relative() {
+        if let Ok(current_dir) = env::current_dir() {
+            root_pathbuf = current_dir.join(root_pathbuf);
+        }
+    }
+    env::set_var(SCAII_HOME_VAR, root_pathbuf.as_os_str());
 }
 
-fn parse_args(arguments: &Vec<String>) -> Args {
-    let mut args = Args {
-        flag_branch: false,
-        arg_branch_name: "".to_string(),
-        compile_type: "".to_string(),
-    };
-    if arguments.len() > 1 {
-        if arguments[1] == "install" {
-            if arguments.len() == 2 {
-                args.flag_branch = true;
-                args.arg_branch_name = "master".to_string();
-                args.compile_type = "--release".to_string();
-                println!("No branch specified, defaulting to 'master'");
-            } else if arguments.len() == 3 {
-                args.flag_branch = true;
-                args.arg_branch_name = arguments[2].clone();
-                args.compile_type = "--release".to_string();
-            } else if arguments.len() == 4 {
-                args.flag_branch = true;
-                args.arg_branch_name = arguments[2].clone();
-                if arguments[3] != "debug" {
-                    args.compile_type = format!("--{}", arguments[3].clone().to_string());
+fn try_command(command: &String, mut args: Args) -> Result<(), Box<Error>> {
+    use error::InstallError;
+    use platform::*;
+
+    let orig_dir_pathbuf = env::current_dir()?;
+    // handled before the settings are loaded, so that a bad setting can still be fixed
+    if command == "config" {
+        return match args.arg_config_action.as_ref() {
+            "get" if args.arg_config_key != "" => {
+                config::get(&orig_dir_pathbuf, &args.arg_config_key)
+            }
+            "set" if args.arg_config_value != "" => config::set(
+                &orig_dir_pathbuf,
+                &args.arg_config_key,
+                &args.arg_config_value,
+                args.flag_project,
+            ),
+            "list" => config::list(&orig_dir_pathbuf),
+            _ => Err(Box::new(InstallError::Usage(
+                "expected 'config get <key>', 'config set <key> <value> [--project]' or \
+             'config list'."
+                    .to_string(),
+            ))),
+        };
+    }
+    let config = Config::load(&orig_dir_pathbuf, env::home_dir(), &config::env_vars())?;
+    config.apply_proxy();
+    let dot_scaii = config.dot_scaii.clone();
+    staging::recover_interrupted_swap(&dot_scaii)?;
+    versions::migrate_legacy_install(&dot_scaii)?;
+    match command.as_ref() {
+        "install" => {
+            doctor::check_before_install(&config, !args.flag_prebuilt, args.arg_from_bundle == "")?;
+            if args.arg_from_bundle != "" {
+                if args.arg_source != ""
+                    || args.flag_locked
+                    || args.arg_rev != ""
+                    || args.arg_sky_rts_rev != ""
+                {
+                    return Err(Box::new(InstallError::Usage(
+                        "--from-bundle can't be combined with --source, --locked, --rev or \
+                         --sky-rts-rev."
+                            .to_string(),
+                    )));
                 }
+                return install_from_bundle(&dot_scaii, &orig_dir_pathbuf, &config, args);
             }
-        } else if arguments[1] == "reinstall" {
-            if arguments.len() == 3 {
-                if arguments[2] != "debug" {
-                    args.compile_type = format!("--{}", arguments[2].clone().to_string());
+            let source = if args.arg_source != "" {
+                Some(args.arg_source.clone())
+            } else {
+                config.source.clone()
+            };
+            let lock = if args.flag_locked {
+                if args.arg_source != "" || args.arg_rev != "" || args.arg_sky_rts_rev != "" {
+                    return Err(Box::new(InstallError::Usage(
+                        "--locked can't be combined with --source, --rev or --sky-rts-rev."
+                            .to_string(),
+                    )));
                 }
+                let lock = Lockfile::load(&orig_dir_pathbuf.join(LOCKFILE_NAME))?;
+                apply_lockfile(&lock, &mut args)?;
+                Some(lock)
             } else {
-                args.compile_type = "--release".to_string();
+                apply_install_defaults(&config, &mut args);
+                None
+            };
+            if args.flag_prebuilt {
+                if source.is_some() {
+                    return Err(Box::new(InstallError::Usage(
+                        "--prebuilt can't be used while the source setting names a checkout \
+                         to build."
+                            .to_string(),
+                    )));
+                }
+                if install_prebuilt(&dot_scaii, &orig_dir_pathbuf, &config, &args)? {
+                    return Ok(());
+                }
+                // falling back to building from source needs what was skipped above
+                doctor::check_before_install(&config, true, true)?;
+            }
+            if let Some(source) = source {
+                if lock.is_none() {
+                    return install_from_source(
+                        &dot_scaii,
+                        &orig_dir_pathbuf,
+                        source,
+                        &config,
+                        &args,
+                    );
+                }
+            }
+            let name = versions::version_name(&args.arg_branch_name, &args.compile_type);
+            println!("installing version {}", name);
+            let staging = staging::prepare_staging_dir(&dot_scaii)?;
+            let staged_install_dir = staging.join("git");
+            common::ensure_dir_exists(&staged_install_dir)?;
+            let scaii_dir = staged_install_dir.join("SCAII");
+            let result = get_core(staged_install_dir.clone(), &args, &config, lock.as_ref())
+                .and_then(|_| checkout_sky_rts_rev(&scaii_dir, &args))
+                .and_then(|_| match lock {
+                    Some(ref lock) => verify_locked_checkout(&scaii_dir, lock),
+                    None => Ok(()),
+                })
+                .and_then(|_| build_all(&scaii_dir, &staging, &args, &config))
+                .and_then(|_| {
+                    save_version_info(
+                        &scaii_dir,
+                        &staging,
+                        &name,
+                        &args.arg_branch_name,
+                        None,
+                        &args,
+                    )
+                });
+            finish_staged_install(&dot_scaii, &name, &orig_dir_pathbuf, result)
+        }
+        "reinstall" => {
+            let name = match versions::read_active(&dot_scaii)? {
+                Some(name) => name,
+                None => {
+                    return Err(Box::new(InstallError::Prerequisite(
+                        "Installation not found. Nothing to reinstall.".to_string(),
+                    )))
+                }
+            };
+            let version_dir = versions::get_version_dir(&dot_scaii, &name)?;
+            let info = VersionInfo::load(&version_dir)?;
+            if info.as_ref().map_or(false, |info| info.prebuilt.is_some()) {
+                return Err(Box::new(InstallError::Usage(format!(
+                    "{} was installed prebuilt, so there is nothing to recompile - run \
+                     'install --prebuilt' to fetch it again.",
+                    name
+                ))));
+            }
+            let (branch, source) = match info {
+                Some(info) => {
+                    // keep recording the revisions the checkout was pinned to
+                    args.arg_rev = info.rev.unwrap_or_default();
+                    args.arg_sky_rts_rev = info.sky_rts_rev.unwrap_or_default();
+                    (info.branch, info.source)
+                }
+                None => (name.clone(), None),
+            };
+            // versions installed with --source build from the user's own checkout
+            let scaii_dir = match source {
+                Some(ref source) => PathBuf::from(source),
+                None => get_default_install_dir(&dot_scaii)?.join("SCAII"),
+            };
+            if !(scaii_dir.exists()) {
+                return Err(Box::new(InstallError::Prerequisite(
+                    "Installation not found. Nothing to reinstall.".to_string(),
+                )));
+            }
+            println!("Reinstalling version {}.", name);
+            // the checkout stays where it is, only the build outputs are staged
+            let staging = staging::prepare_staging_dir(&dot_scaii)?;
+            let result = build_all(&scaii_dir, &staging, &args, &config).and_then(|_| {
+                save_version_info(&scaii_dir, &staging, &name, &branch, source.clone(), &args)
+            });
+            finish_staged_install(&dot_scaii, &name, &orig_dir_pathbuf, result)
+        }
+        "update" => update_active_version(&dot_scaii, &orig_dir_pathbuf, &config, args),
+        "uninstall" => {
+            if args.arg_version_name != "" {
+                return uninstall_version(&dot_scaii, &args.arg_version_name);
+            }
+            for name in versions::list_versions(&dot_scaii)? {
+                uninstall_version(&dot_scaii, &name)?;
+            }
+            versions::deactivate(&dot_scaii)?;
+            staging::discard_staging_dir(&dot_scaii)?;
+            staging::discard_previous_install(&dot_scaii)?;
+            Ok(())
+        }
+        "rollback" => {
+            println!("Rolling back to the previous install.");
+            let active_before = versions::read_active(&dot_scaii)?;
+            match staging::rollback(&dot_scaii, active_before)? {
+                Some(name) => versions::activate(&dot_scaii, &name),
+                None => versions::deactivate(&dot_scaii),
             }
-        } else if arguments[1] == "uninstall" {
         }
+        "use" => versions::activate(&dot_scaii, &args.arg_version_name),
+        "list" => list_versions(&dot_scaii),
+        "status" => status::run(&dot_scaii),
+        "doctor" => doctor::run(&config),
+        "lock" => write_lockfile(&dot_scaii, &orig_dir_pathbuf),
+        "bundle" => match args.arg_bundle_action.as_ref() {
+            "create" if args.arg_bundle_path != "" => create_bundle(
+                &dot_scaii,
+                &orig_dir_pathbuf,
+                &config,
+                &args.arg_bundle_path,
+            ),
+            _ => Err(Box::new(InstallError::Usage(
+                "expected 'bundle create <out.tar.gz>'.".to_string(),
+            ))),
+        },
+        "cache" => match args.arg_cache_action.as_ref() {
+            "list" => cache::list(&config),
+            "clean" => cache::clean(&config),
+            "prune" => cache::prune(&config, cache::parse_age_days(&args.arg_older_than)?),
+            _ => Err(Box::new(InstallError::Usage(format!(
+                "unknown cache action '{}' - expected list, clean or prune.",
+                args.arg_cache_action
+            )))),
+        },
+        _ => Err(Box::new(InstallError::Usage(format!(
+            "unknown command {}",
+            command
+        )))),
+    }
+}
+
+// swaps a successfully staged install into its version dir and activates it, or throws
+// it away on failure so the installed versions are left exactly as they were
+fn finish_staged_install(
+    dot_scaii: &PathBuf,
+    version_name: &str,
+    orig_dir_pathbuf: &PathBuf,
+    result: Result<(), Box<Error>>,
+) -> Result<(), Box<Error>> {
+    env::set_current_dir(orig_dir_pathbuf)?;
+    match result {
+        Ok(()) => {
+            let active_before = versions::read_active(dot_scaii)?;
+            staging::promote_staging_dir(dot_scaii, version_name, active_before)?;
+            versions::activate(dot_scaii, version_name)
+        }
+        Err(err) => {
+            println!("install failed, leaving the existing install in place.");
+            staging::discard_staging_dir(dot_scaii)?;
+            Err(err)
+        }
+    }
+}
+
+// brings the active version's checkout up to date in place rather than recloning it, and
+// rebuilds only if the checkout moved.  Cargo reuses the checkout's target dir, so only
+// the crates that changed are recompiled.  Switching branch renames the version.
+fn update_active_version(
+    dot_scaii: &PathBuf,
+    orig_dir_pathbuf: &PathBuf,
+    config: &Config,
+    mut args: Args,
+) -> Result<(), Box<Error>> {
+    use error::InstallError;
+    use platform::common;
+
+    let name = match versions::read_active(dot_scaii)? {
+        Some(name) => name,
+        None => {
+            return Err(Box::new(InstallError::Prerequisite(
+                "no version is active - run 'install' first.".to_string(),
+            )))
+        }
+    };
+    let version_dir = versions::get_version_dir(dot_scaii, &name)?;
+    let scaii_dir = version_dir.join("git").join("SCAII");
+    let info = match VersionInfo::load(&version_dir)? {
+        Some(info) => info,
+        None => {
+            let branch = platform::get_current_branch(&scaii_dir)?;
+            VersionInfo::new(&name, &branch, &"--release".to_string(), String::new())
+        }
+    };
+    if info.source.is_some() {
+        return Err(Box::new(InstallError::Usage(format!(
+            "{} was installed from {} - update that checkout yourself and run 'reinstall'.",
+            name,
+            info.source.unwrap()
+        ))));
+    }
+    if info.prebuilt.is_some() {
+        return Err(Box::new(InstallError::Usage(format!(
+            "{} was installed prebuilt and has no checkout to update - run 'install \
+             --prebuilt' to fetch the latest build.",
+            name
+        ))));
+    }
+    if !scaii_dir.exists() {
+        return Err(Box::new(InstallError::Prerequisite(format!(
+            "the checkout for {} is missing - run 'install' instead.",
+            name
+        ))));
+    }
+    if !args.flag_branch {
+        args.arg_branch_name = info.branch.clone();
+        // a pinned version stays on its revision unless a branch or --rev is given
+        if args.arg_rev == "" {
+            args.arg_rev = info.rev.clone().unwrap_or_default();
+        }
+    }
+    if args.arg_sky_rts_rev == "" {
+        args.arg_sky_rts_rev = info.sky_rts_rev.clone().unwrap_or_default();
+    }
+    if !args.flag_profile && info.profile == "release" {
+        args.compile_type = "--release".to_string();
+    }
+    let new_name = versions::version_name(&args.arg_branch_name, &args.compile_type);
+    let new_version_dir = versions::get_version_dir(dot_scaii, &new_name)?;
+    if new_name != name && new_version_dir.exists() {
+        return Err(Box::new(InstallError::Usage(format!(
+            "version {} is already installed - run 'use {}' or 'update' it instead.",
+            new_name, new_name
+        ))));
+    }
+
+    println!("updating version {}", name);
+    let target = if args.arg_rev != "" {
+        args.arg_rev.clone()
     } else {
-        usage();
-        std::process::exit(0);
+        args.arg_branch_name.clone()
+    };
+    platform::update_checkout(&scaii_dir, &target, &common::submodule_urls(config))?;
+    checkout_sky_rts_rev(&scaii_dir, &args)?;
+    prepare_source_checkout(&scaii_dir, config, false)?;
+    let commit = platform::get_head_commit(&scaii_dir)?;
+    let sky_rts_commit = get_sky_rts_commit(&scaii_dir)?;
+    if new_name == name && commit == info.commit && Some(sky_rts_commit) == info.sky_rts_commit {
+        println!("{} is already up to date.", name);
+        return Ok(());
+    }
+
+    let staging = staging::prepare_staging_dir(dot_scaii)?;
+    let result = build_all(&scaii_dir, &staging, &args, config).and_then(|_| {
+        save_version_info(
+            &scaii_dir,
+            &staging,
+            &new_name,
+            &args.arg_branch_name,
+            None,
+            &args,
+        )
+    });
+    if result.is_ok() && new_name != name {
+        println!("renaming version {} to {}", name, new_name);
+        versions::deactivate(dot_scaii)?;
+        fs::rename(&version_dir, &new_version_dir)?;
     }
-    args
+    finish_staged_install(dot_scaii, &new_name, orig_dir_pathbuf, result)
 }
 
-fn try_command(command: &String, args: Args) -> Result<(), Box<Error>> {
-    use platform::*;
+// builds an existing SCAII checkout instead of cloning one.  The checkout belongs to
+// the user, so it is only ever built in, never cleaned or removed.
+fn install_from_source(
+    dot_scaii: &PathBuf,
+    orig_dir_pathbuf: &PathBuf,
+    source: String,
+    config: &Config,
+    args: &Args,
+) -> Result<(), Box<Error>> {
+    use error::InstallError;
 
-    let install_dir = get_default_install_dir()?;
-    match command.as_ref() {
-        "install" => {
-            try_clean_core_all(install_dir.clone())?;
-            get_core(install_dir.clone(), &args)?;
-            build_core(&install_dir, args.compile_type.clone())?;
-            build_sky_rts(install_dir.clone())?;
-            copy_execs(install_dir.clone(), args.compile_type == "--release")?;
-            Ok(())
+    let scaii_dir = orig_dir_pathbuf.join(source);
+    if !scaii_dir.join("Cargo.toml").exists() || !scaii_dir.join("viz").exists() {
+        return Err(Box::new(InstallError::Usage(format!(
+            "{:?} does not look like a SCAII checkout.",
+            scaii_dir
+        ))));
+    }
+    let dir_name = match scaii_dir.file_name() {
+        Some(dir_name) => dir_name.to_string_lossy().to_string(),
+        None => "SCAII".to_string(),
+    };
+    let name = versions::version_name(&format!("local-{}", dir_name), &args.compile_type);
+    println!("installing version {} from {:?}", name, scaii_dir);
+    let branch = platform::get_current_branch(&scaii_dir)?;
+    let staging = staging::prepare_staging_dir(dot_scaii)?;
+    let result = prepare_source_checkout(&scaii_dir, config, true)
+        .and_then(|_| build_all(&scaii_dir, &staging, args, config))
+        .and_then(|_| {
+            save_version_info(
+                &scaii_dir,
+                &staging,
+                &name,
+                &branch,
+                Some(scaii_dir.to_string_lossy().to_string()),
+                args,
+            )
+        });
+    finish_staged_install(dot_scaii, &name, orig_dir_pathbuf, result)
+}
+
+// installs the binaries the prebuilt index lists for the branch and profile on this
+// platform, returning false when it lists none so that they are built from source instead
+fn install_prebuilt(
+    dot_scaii: &PathBuf,
+    orig_dir_pathbuf: &PathBuf,
+    config: &Config,
+    args: &Args,
+) -> Result<bool, Box<Error>> {
+    if !config.extra_backends.is_empty() {
+        println!("extra_backends have to be built from a checkout, so building from source.");
+        return Ok(false);
+    }
+    let build = match prebuilt::find(config, &args.arg_branch_name, &args.compile_type)? {
+        Some(build) => build,
+        None => {
+            println!(
+                "no prebuilt {} {} build for {} - building from source.",
+                args.arg_branch_name,
+                versions::profile_name(&args.compile_type),
+                prebuilt::target_triple()
+            );
+            return Ok(false);
         }
-        "reinstall" => {
-            if !(install_dir.exists()) {
-                println!("ERROR: Installation not found. Nothing to reinstall.");
-                std::process::exit(0);
-            } else {
-                println!("Reinstalling Sky-RTS.");
-                shallow_clean();
+    };
+    let name = versions::version_name(&args.arg_branch_name, &args.compile_type);
+    println!("installing prebuilt version {}", name);
+    let staging = staging::prepare_staging_dir(dot_scaii)?;
+    let result = prebuilt::install(&build, &staging, config).and_then(|_| {
+        let mut info = VersionInfo::new(
+            &name,
+            &args.arg_branch_name,
+            &args.compile_type,
+            build.commit.clone(),
+        );
+        info.sky_rts_commit = Some(build.sky_rts_commit.clone().unwrap_or(build.commit.clone()));
+        info.prebuilt = Some(build.url.clone());
+        info.save(&staging)
+    });
+    finish_staged_install(dot_scaii, &name, orig_dir_pathbuf, result)?;
+    Ok(true)
+}
+
+// a fresh clone gets the viz javascript dependencies from get_core and an update
+// replaces the ones that are outdated, but a user's checkout only gets the ones it is
+// missing - whatever it already has is left alone, with or without a version marker
+fn prepare_source_checkout(
+    scaii_dir: &PathBuf,
+    config: &Config,
+    users_checkout: bool,
+) -> Result<(), Box<Error>> {
+    use platform::common;
+
+    let js_dir = scaii_dir.join("viz").join("js");
+    if !users_checkout || !js_dir.join("closure-library").exists() {
+        common::ensure_google_closure_lib_installed(scaii_dir.clone(), config, None)?;
+    }
+    if !users_checkout || !js_dir.join("protobuf_js").exists() {
+        common::ensure_protobuf_javascript_lib_installed(scaii_dir.clone(), config, None)?;
+    }
+    Ok(())
+}
+
+fn save_version_info(
+    scaii_dir: &PathBuf,
+    dest_root: &PathBuf,
+    name: &str,
+    branch: &str,
+    source: Option<String>,
+    args: &Args,
+) -> Result<(), Box<Error>> {
+    let commit = platform::get_head_commit(scaii_dir)?;
+    let mut info = VersionInfo::new(name, branch, &args.compile_type, commit);
+    info.source = source;
+    if args.arg_rev != "" {
+        info.rev = Some(args.arg_rev.clone());
+    }
+    if args.arg_sky_rts_rev != "" {
+        info.sky_rts_rev = Some(args.arg_sky_rts_rev.clone());
+    }
+    info.sky_rts_commit = Some(get_sky_rts_commit(scaii_dir)?);
+    info.save(dest_root)
+}
+
+// Sky-RTS is built from backends/sky-rts in the SCAII checkout, which can only be moved
+// to a revision of its own when it is a separate repository (a submodule)
+fn checkout_sky_rts_rev(scaii_dir: &PathBuf, args: &Args) -> Result<(), Box<Error>> {
+    use error::InstallError;
+
+    if args.arg_sky_rts_rev == "" {
+        return Ok(());
+    }
+    let sky_rts_dir = scaii_dir.join("backends").join("sky-rts");
+    if !sky_rts_dir.join(".git").exists() {
+        return Err(Box::new(InstallError::Usage(format!(
+            "Sky-RTS is part of the SCAII repository at {:?}, so it can't be pinned \
+             separately - use --rev instead.",
+            scaii_dir
+        ))));
+    }
+    println!("checking out Sky-RTS {}", args.arg_sky_rts_rev);
+    platform::update_checkout(&sky_rts_dir, &args.arg_sky_rts_rev, &Vec::new())
+}
+
+// fills in the branch and profile the command line left out of an install from config,
+// else the defaults
+fn apply_install_defaults(config: &Config, args: &mut Args) {
+    let source_install = args.arg_source != "" || config.source.is_some();
+    if !args.flag_branch && !source_install {
+        args.flag_branch = true;
+        args.arg_branch_name = match config.branch {
+            Some(ref branch) => branch.clone(),
+            None => {
+                println!("No branch specified, defaulting to 'master'");
+                "master".to_string()
             }
-            build_core(&install_dir, args.compile_type.clone())?;
-            build_sky_rts(install_dir.clone())?;
-            copy_execs(install_dir.clone(), args.compile_type == "--release")?;
-            Ok(())
+        };
+    }
+    if !args.flag_profile {
+        if let Some(ref profile) = config.profile {
+            args.compile_type = versions::compile_type(profile);
         }
-        "uninstall" => {
-            try_clean_core_all(install_dir.clone())?;
-            try_clean_sky_rts_all(install_dir)?;
-            shallow_clean();
-            Ok(())
+    }
+}
+
+// installs the SCAII and Sky-RTS commits the lockfile pins, and its branch and profile
+// unless they were given
+fn apply_lockfile(lock: &Lockfile, args: &mut Args) -> Result<(), Box<Error>> {
+    use lockfile::{ARTIFACT_SCAII, ARTIFACT_SKY_RTS};
+
+    let scaii = lock.artifact(ARTIFACT_SCAII)?;
+    let sky_rts = lock.artifact(ARTIFACT_SKY_RTS)?;
+    args.arg_rev = scaii.commit.clone().unwrap_or_default();
+    if sky_rts.commit != scaii.commit {
+        args.arg_sky_rts_rev = sky_rts.commit.clone().unwrap_or_default();
+    }
+    if !args.flag_branch {
+        args.flag_branch = true;
+        args.arg_branch_name = lock.branch.clone();
+    }
+    if !args.flag_profile {
+        args.compile_type = if lock.profile == "release" {
+            "--release".to_string()
+        } else {
+            "".to_string()
+        };
+    }
+    println!(
+        "installing from {}: SCAII {}, Sky-RTS {}",
+        LOCKFILE_NAME,
+        args.arg_rev,
+        sky_rts.commit.clone().unwrap_or_default()
+    );
+    Ok(())
+}
+
+fn verify_locked_checkout(scaii_dir: &PathBuf, lock: &Lockfile) -> Result<(), Box<Error>> {
+    use lockfile::{ARTIFACT_SCAII, ARTIFACT_SKY_RTS};
+
+    let (scaii, sky_rts) = get_checkout_artifacts(scaii_dir)?;
+    lock.artifact(ARTIFACT_SCAII)?.verify(&scaii)?;
+    lock.artifact(ARTIFACT_SKY_RTS)?.verify(&sky_rts)
+}
+
+// the SCAII and Sky-RTS repositories as checked out in scaii_dir
+fn get_checkout_artifacts(scaii_dir: &PathBuf) -> Result<(Artifact, Artifact), Box<Error>> {
+    use lockfile::{ARTIFACT_SCAII, ARTIFACT_SKY_RTS};
+
+    let sky_rts_dir = scaii_dir.join("backends").join("sky-rts");
+    let sky_rts_repo = if sky_rts_dir.join(".git").exists() {
+        sky_rts_dir
+    } else {
+        scaii_dir.clone()
+    };
+    let mut scaii = Artifact::new(ARTIFACT_SCAII, &platform::get_remote_url(scaii_dir)?);
+    scaii.commit = Some(platform::get_head_commit(scaii_dir)?);
+    let mut sky_rts = Artifact::new(ARTIFACT_SKY_RTS, &platform::get_remote_url(&sky_rts_repo)?);
+    sky_rts.commit = Some(platform::get_head_commit(&sky_rts_repo)?);
+    Ok((scaii, sky_rts))
+}
+
+// writes sky-install.lock for the active version into the current directory
+fn write_lockfile(dot_scaii: &PathBuf, orig_dir_pathbuf: &PathBuf) -> Result<(), Box<Error>> {
+    let (lock, _, name) = get_active_version_lock(dot_scaii)?;
+    let path = orig_dir_pathbuf.join(LOCKFILE_NAME);
+    lock.save(&path)?;
+    println!("wrote {:?} for version {}", path, name);
+    Ok(())
+}
+
+// the lockfile pinning the active version, along with its checkout and name
+fn get_active_version_lock(dot_scaii: &PathBuf) -> Result<(Lockfile, PathBuf, String), Box<Error>> {
+    use error::InstallError;
+
+    let name = match versions::read_active(dot_scaii)? {
+        Some(name) => name,
+        None => {
+            return Err(Box::new(InstallError::Prerequisite(
+                "no version is active - run 'install' first.".to_string(),
+            )))
         }
-        _ => {
-            println!("Unknown command:  {}", command);
-            usage();
-            Ok(())
+    };
+    let version_dir = versions::get_version_dir(dot_scaii, &name)?;
+    let info = match VersionInfo::load(&version_dir)? {
+        Some(info) => info,
+        None => {
+            return Err(Box::new(InstallError::Prerequisite(format!(
+                "{} predates install records - run 'update' or 'install' first.",
+                name
+            ))))
+        }
+    };
+    if info.source.is_some() {
+        return Err(Box::new(InstallError::Usage(format!(
+            "{} was installed from a local checkout, which can't be locked.",
+            name
+        ))));
+    }
+    let scaii_dir = version_dir.join("git").join("SCAII");
+    let (scaii, sky_rts) = get_checkout_artifacts(&scaii_dir)?;
+    let mut lock = Lockfile {
+        branch: info.branch.clone(),
+        profile: info.profile.clone(),
+        artifacts: vec![scaii, sky_rts],
+    };
+    lock.artifacts.extend(get_javascript_artifacts(&scaii_dir)?);
+    Ok((lock, scaii_dir, name))
+}
+
+// the closure library and protobuf javascript installed in the checkout, as recorded
+// by their version markers
+fn get_javascript_artifacts(scaii_dir: &PathBuf) -> Result<Vec<Artifact>, Box<Error>> {
+    use error::InstallError;
+    use lockfile::{ARTIFACT_CLOSURE_LIBRARY, ARTIFACT_PROTOBUF_JS};
+    use platform::common;
+
+    let js_dir = scaii_dir.join("viz").join("js");
+    let mut artifacts = Vec::new();
+    for &(artifact_name, dir_name) in [
+        (ARTIFACT_CLOSURE_LIBRARY, "closure-library"),
+        (ARTIFACT_PROTOBUF_JS, "protobuf_js"),
+    ]
+    .iter()
+    {
+        match common::read_artifact_marker(&js_dir.join(dir_name))? {
+            Some(ref artifact) if artifact.url != "" => artifacts.push(artifact.clone()),
+            _ => {
+                return Err(Box::new(InstallError::Prerequisite(format!(
+                    "{} was installed before downloads were recorded - run 'install' again \
+                     to lock it.",
+                    artifact_name
+                ))))
+            }
+        }
+    }
+    Ok(artifacts)
+}
+
+fn create_bundle(
+    dot_scaii: &PathBuf,
+    orig_dir_pathbuf: &PathBuf,
+    config: &Config,
+    bundle_path: &str,
+) -> Result<(), Box<Error>> {
+    bundle::refuse_extra_backends(config)?;
+    let (lock, scaii_dir, name) = get_active_version_lock(dot_scaii)?;
+    let out_path = orig_dir_pathbuf.join(bundle_path);
+    println!("bundling version {} into {:?}", name, out_path);
+    let work_dir = dot_scaii.join("bundle-work");
+    bundle::create(&lock, &scaii_dir, &work_dir, &out_path)?;
+    println!(
+        "wrote {:?} - install it with 'install --from-bundle {}'",
+        out_path, bundle_path
+    );
+    Ok(())
+}
+
+// installs the version a bundle holds without touching the network
+fn install_from_bundle(
+    dot_scaii: &PathBuf,
+    orig_dir_pathbuf: &PathBuf,
+    config: &Config,
+    mut args: Args,
+) -> Result<(), Box<Error>> {
+    use bundle::BUNDLE_SCAII_DIRNAME;
+
+    bundle::refuse_extra_backends(config)?;
+    let bundle_path = orig_dir_pathbuf.join(&args.arg_from_bundle);
+    let staging = staging::prepare_staging_dir(dot_scaii)?;
+    let staged_install_dir = staging.join("git");
+    let unpacked = bundle::unpack(&bundle_path, &staged_install_dir)
+        .and_then(|lock| apply_lockfile(&lock, &mut args).map(|_| lock));
+    let lock = match unpacked {
+        Ok(lock) => lock,
+        Err(err) => {
+            staging::discard_staging_dir(dot_scaii)?;
+            return Err(err);
+        }
+    };
+    let name = versions::version_name(&args.arg_branch_name, &args.compile_type);
+    println!("installing version {} from {:?}", name, bundle_path);
+    // every crate is vendored in the bundle, so cargo must not try to update its index
+    env::set_var("CARGO_NET_OFFLINE", "true");
+    let scaii_dir = staged_install_dir.join(BUNDLE_SCAII_DIRNAME);
+    let result = verify_locked_checkout(&scaii_dir, &lock)
+        .and_then(|_| verify_bundled_javascript(&scaii_dir, &lock))
+        .and_then(|_| build_all(&scaii_dir, &staging, &args, config))
+        .and_then(|_| {
+            save_version_info(
+                &scaii_dir,
+                &staging,
+                &name,
+                &args.arg_branch_name,
+                None,
+                &args,
+            )
+        });
+    finish_staged_install(dot_scaii, &name, orig_dir_pathbuf, result)
+}
+
+fn verify_bundled_javascript(scaii_dir: &PathBuf, lock: &Lockfile) -> Result<(), Box<Error>> {
+    for artifact in get_javascript_artifacts(scaii_dir)? {
+        lock.artifact(&artifact.name)?.verify(&artifact)?;
+    }
+    Ok(())
+}
+
+fn get_sky_rts_commit(scaii_dir: &PathBuf) -> Result<String, Box<Error>> {
+    let sky_rts_dir = scaii_dir.join("backends").join("sky-rts");
+    if sky_rts_dir.join(".git").exists() {
+        platform::get_head_commit(&sky_rts_dir)
+    } else {
+        platform::get_head_commit(scaii_dir)
+    }
+}
+
+fn list_versions(dot_scaii: &PathBuf) -> Result<(), Box<Error>> {
+    let active = versions::read_active(dot_scaii)?;
+    let names = versions::list_versions(dot_scaii)?;
+    if names.is_empty() {
+        println!("No versions installed.");
+    }
+    for name in names {
+        let marker = if active.as_ref() == Some(&name) {
+            "*"
+        } else {
+            " "
+        };
+        let version_dir = versions::get_version_dir(dot_scaii, &name)?;
+        match VersionInfo::load(&version_dir)? {
+            Some(info) => {
+                let short_commit: String = info.commit.chars().take(8).collect();
+                println!(
+                    "{} {}    branch {}, {}, commit {}",
+                    marker, name, info.branch, info.profile, short_commit
+                );
+                if let Some(ref rev) = info.rev {
+                    println!("      pinned to {}", rev);
+                }
+                if let Some(ref sky_rts_commit) = info.sky_rts_commit {
+                    if sky_rts_commit != &info.commit {
+                        println!("      Sky-RTS commit {}", sky_rts_commit);
+                    }
+                }
+            }
+            None => println!("{} {}", marker, name),
+        }
+    }
+    Ok(())
+}
+
+// removes one version's checkout and the files its manifest lists.  Files the user
+// modified are left behind, along with the version dir holding them.
+fn uninstall_version(dot_scaii: &PathBuf, name: &str) -> Result<(), Box<Error>> {
+    let version_dir = versions::get_installed_version_dir(dot_scaii, name)?;
+    println!("uninstalling version {}", name);
+    if versions::read_active(dot_scaii)? == Some(name.to_string()) {
+        versions::deactivate(dot_scaii)?;
+    }
+    try_clean_core_all(version_dir.clone())?;
+    try_clean_sky_rts_all(version_dir.clone())?;
+    shallow_clean(&version_dir)?;
+
+    let info = version_dir.join(versions::INFO_FILENAME);
+    if info.as_path().exists() {
+        fs::remove_file(&info)?;
+    }
+    remove_dir_if_empty(&version_dir.join("git"))?;
+    remove_dir_if_empty(&version_dir)?;
+    if version_dir.as_path().exists() {
+        println!("leaving modified files in {:?}", version_dir);
+    }
+    Ok(())
+}
+
+fn remove_dir_if_empty(dir: &PathBuf) -> Result<(), Box<Error>> {
+    if dir.as_path().exists() && fs::read_dir(dir)?.next().is_none() {
+        fs::remove_dir(dir)?;
+    }
+    Ok(())
+}
+
+// builds every component from the SCAII checkout in scaii_dir into dest_root (a version
+// dir or the staging dir) and records what was written in the install manifest
+fn build_all(
+    scaii_dir: &PathBuf,
+    dest_root: &PathBuf,
+    args: &Args,
+    config: &Config,
+) -> Result<(), Box<Error>> {
+    let commit = platform::get_head_commit(scaii_dir)?;
+    let mut manifest = Manifest::new(commit);
+    let result = build_all_into_manifest(scaii_dir, dest_root, args, config, &mut manifest);
+    // save whatever was written, even on failure, so a later clean can remove it
+    manifest.save(dest_root)?;
+    result
+}
+
+fn build_all_into_manifest(
+    scaii_dir: &PathBuf,
+    dest_root: &PathBuf,
+    args: &Args,
+    config: &Config,
+    manifest: &mut Manifest,
+) -> Result<(), Box<Error>> {
+    build_core(
+        scaii_dir,
+        dest_root,
+        args.compile_type.clone(),
+        &config.cargo_features,
+        manifest,
+    )?;
+    build_sky_rts(
+        scaii_dir.clone(),
+        dest_root,
+        args.compile_type.clone(),
+        manifest,
+    )?;
+    build_extra_backends(scaii_dir, dest_root, &args.compile_type, config, manifest)?;
+    copy_execs(
+        scaii_dir,
+        dest_root,
+        args.compile_type == "--release",
+        manifest,
+    )?;
+    Ok(())
+}
+
+// clones each backend config.toml lists into SCAII's backends dir, unless it is already
+// there, builds it and installs the libraries it builds into backends/bin
+fn build_extra_backends(
+    scaii_dir: &PathBuf,
+    dest_root: &PathBuf,
+    compile_type: &String,
+    config: &Config,
+    manifest: &mut Manifest,
+) -> Result<(), Box<Error>> {
+    use error::InstallError;
+    use manifest::COMPONENT_EXTRA_BACKENDS;
+    use platform::common;
+    use std::env::consts::DLL_EXTENSION;
+
+    let bin_dir = dest_root.join("backends").join("bin");
+    for backend in config.extra_backends.iter() {
+        println!("building backend {}...", backend.name);
+        let backend_dir = scaii_dir.join("backends").join(&backend.name);
+        if !backend_dir.as_path().exists() {
+            let target = backend.rev.as_ref().map(|rev| rev.as_str());
+            platform::clone_repo(
+                &vec![backend.url.clone()],
+                &backend_dir,
+                target,
+                &Vec::new(),
+            )?;
+        }
+        let crate_dir = match backend.crate_dir {
+            Some(ref crate_dir) => backend_dir.join(crate_dir),
+            None => backend_dir.clone(),
+        };
+        let orig_dir_pathbuf = env::current_dir()?;
+        env::set_current_dir(&crate_dir)?;
+        let built = cargo_build(compile_type, &Vec::new());
+        env::set_current_dir(&orig_dir_pathbuf)?;
+        built?;
+
+        // a crate inside a workspace builds into the workspace's target dir
+        let mut target_dir = crate_dir.join("target");
+        if !target_dir.as_path().exists() {
+            target_dir = backend_dir.join("target");
+        }
+        target_dir.push(versions::profile_name(compile_type));
+        common::ensure_dir_exists(&bin_dir)?;
+        let mut installed = 0;
+        if target_dir.as_path().exists() {
+            for entry in fs::read_dir(&target_dir)? {
+                let path = entry?.path();
+                if path.extension().map_or(false, |ext| ext == DLL_EXTENSION) {
+                    let dest = bin_dir.join(path.file_name().unwrap());
+                    common::copy_file(&path, &dest)?;
+                    manifest.record_tree(dest_root, &dest, COMPONENT_EXTRA_BACKENDS)?;
+                    installed += 1;
+                }
+            }
+        }
+        if installed == 0 {
+            return Err(Box::new(InstallError::build(
+                &crate_dir,
+                format!(
+                    "ERROR - backend {} built no .{} libraries in {:?}",
+                    backend.name, DLL_EXTENSION, target_dir
+                ),
+            )));
         }
     }
+    Ok(())
 }
 
-fn copy_execs(mut install_path: PathBuf, release: bool) -> Result<(), Box<Error>> {
+fn copy_execs(
+    scaii_dir: &PathBuf,
+    dest_root: &PathBuf,
+    release: bool,
+    manifest: &mut Manifest,
+) -> Result<(), Box<Error>> {
+    use manifest::COMPONENT_EXECS;
+
     #[cfg(target_os = "windows")]
     const EXTENSION: &'static str = ".exe";
     #[cfg(not(target_os = "windows"))]
     const EXTENSION: &'static str = "";
 
-    // we want the root
-    install_path.pop();
-
-    fs::create_dir_all(install_path.join("bin/core/src/internal/replay/"))?;
+    fs::create_dir_all(dest_root.join("bin/core/src/internal/replay/"))?;
     fs::copy(
-        install_path.join("git/SCAII/core/src/internal/replay/no_cache_webserver.py"),
-        install_path.join("bin/core/src/internal/replay/no_cache_webserver.py"),
+        scaii_dir.join("core/src/internal/replay/no_cache_webserver.py"),
+        dest_root.join("bin/core/src/internal/replay/no_cache_webserver.py"),
     )?;
 
     let from = vec![
-        install_path.join("git/SCAII/viz"),
-        install_path.join("git/SCAII/cfg.toml"),
-        install_path.join(format!(
-            "git/SCAII/target/{}/replay{}",
+        scaii_dir.join("viz"),
+        scaii_dir.join("cfg.toml"),
+        scaii_dir.join(format!(
+            "target/{}/replay{}",
             if release { "release" } else { "debug" },
             EXTENSION
         )),
     ];
 
-    let to = install_path.join("bin");
+    let to = dest_root.join("bin");
     let opts = fs_extra::dir::CopyOptions {
         overwrite: true,
         skip_exist: false,
@@ -176,15 +1054,25 @@ fn copy_execs(mut install_path: PathBuf, release: bool) -> Result<(), Box<Error>
         copy_inside: true,
         depth: 0,
     };
-    fs_extra::copy_items(&from, to, &opts)?;
+    fs_extra::copy_items(&from, &to, &opts)?;
+
+    manifest.record_tree(
+        dest_root,
+        &dest_root.join("bin/core/src/internal/replay/no_cache_webserver.py"),
+        COMPONENT_EXECS,
+    )?;
+    for item in from.iter() {
+        let copied = to.join(item.file_name().unwrap());
+        manifest.record_tree(dest_root, &copied, COMPONENT_EXECS)?;
+    }
     Ok(())
 }
 
-fn try_clean_core_all(install_dir: PathBuf) -> Result<(), Box<Error>> {
+fn try_clean_core_all(version_dir: PathBuf) -> Result<(), Box<Error>> {
     let mut success: bool = false;
     let mut count = 0;
     while !success {
-        let result = clean_core_all(install_dir.clone());
+        let result = clean_core_all(version_dir.clone());
         match result {
             Ok(_) => {
                 success = true;
@@ -200,10 +1088,18 @@ fn try_clean_core_all(install_dir: PathBuf) -> Result<(), Box<Error>> {
     Ok(())
 }
 
-fn clean_core_build() -> Result<(), Box<Error>> {
+fn clean_core_build(version_dir: &PathBuf) -> Result<(), Box<Error>> {
+    use manifest::COMPONENT_CORE;
+
     println!("removing core build artifacts...");
+    if let Some(mut manifest) = Manifest::load(version_dir)? {
+        manifest.remove_files(version_dir, Some(COMPONENT_CORE))?;
+        return manifest.save(version_dir);
+    }
+
+    // installs without a manifest predate it, so fall back to the known locations
     //rm ~/.scaii/bin/scaii.core
-    let mut scaii_core_path = get_dot_scaii_dir()?;
+    let mut scaii_core_path = version_dir.clone();
     scaii_core_path.push("bin");
     scaii_core_path.push("scaii.core".to_string());
     if scaii_core_path.as_path().exists() {
@@ -212,7 +1108,7 @@ fn clean_core_build() -> Result<(), Box<Error>> {
     }
 
     //rm ~/.scaii/glue
-    let mut glue = get_dot_scaii_dir()?;
+    let mut glue = version_dir.clone();
     glue.push("glue".to_string());
     if glue.as_path().exists() {
         remove_tree(&glue)?;
@@ -220,22 +1116,23 @@ fn clean_core_build() -> Result<(), Box<Error>> {
     Ok(())
 }
 
-fn clean_core_all(install_dir: PathBuf) -> Result<(), Box<Error>> {
+fn clean_core_all(version_dir: PathBuf) -> Result<(), Box<Error>> {
     println!("removing core pull...");
-    let mut scaii_dir = install_dir;
+    let mut scaii_dir = version_dir.clone();
+    scaii_dir.push("git".to_string());
     scaii_dir.push("SCAII".to_string());
     if scaii_dir.as_path().exists() {
         remove_tree(&scaii_dir)?;
     }
-    clean_core_build()?;
+    clean_core_build(&version_dir)?;
     Ok(())
 }
 
-fn try_clean_sky_rts_all(install_dir: PathBuf) -> Result<(), Box<Error>> {
+fn try_clean_sky_rts_all(version_dir: PathBuf) -> Result<(), Box<Error>> {
     let mut success: bool = false;
     let mut count = 0;
     while !success {
-        let result = clean_sky_rts_all(install_dir.clone());
+        let result = clean_sky_rts_all(version_dir.clone());
         match result {
             Ok(_) => {
                 success = true;
@@ -251,10 +1148,18 @@ fn try_clean_sky_rts_all(install_dir: PathBuf) -> Result<(), Box<Error>> {
     Ok(())
 }
 
-fn clean_sky_rts_build() -> Result<(), Box<Error>> {
+fn clean_sky_rts_build(version_dir: &PathBuf) -> Result<(), Box<Error>> {
+    use manifest::COMPONENT_SKY_RTS;
+
     println!("removing Sky-RTS build artifacts...");
+    if let Some(mut manifest) = Manifest::load(version_dir)? {
+        manifest.remove_files(version_dir, Some(COMPONENT_SKY_RTS))?;
+        return manifest.save(version_dir);
+    }
+
+    // installs without a manifest predate it, so fall back to the known locations
     // rm ~/.scaii/backends/bin/libsky-rts.so
-    let mut sky_binary = get_dot_scaii_dir()?;
+    let mut sky_binary = version_dir.clone();
     sky_binary.push("backends".to_string());
     sky_binary.push("bin".to_string());
     sky_binary.push("sky-rts.scm".to_string());
@@ -264,7 +1169,7 @@ fn clean_sky_rts_build() -> Result<(), Box<Error>> {
     }
 
     // ~/.scaii/backends/sky-rts
-    let mut dir = get_dot_scaii_dir()?;
+    let mut dir = version_dir.clone();
     dir.push("backends".to_string());
     dir.push("sky-rts".to_string());
     if dir.as_path().exists() {
@@ -273,46 +1178,52 @@ fn clean_sky_rts_build() -> Result<(), Box<Error>> {
     Ok(())
 }
 
-fn clean_sky_rts_all(install_dir: PathBuf) -> Result<(), Box<Error>> {
+fn clean_sky_rts_all(version_dir: PathBuf) -> Result<(), Box<Error>> {
     println!("removing Sky-RTS...");
-    let mut rts_dir = install_dir;
+    let mut rts_dir = version_dir.clone();
+    rts_dir.push("git".to_string());
     rts_dir.push("Sky-RTS".to_string());
     if rts_dir.as_path().exists() {
         remove_tree(&rts_dir)?;
     }
-    clean_sky_rts_build()?;
+    clean_sky_rts_build(&version_dir)?;
     Ok(())
 }
 
-fn get_dot_scaii_dir() -> Result<PathBuf, Box<Error>> {
-    use platform::common;
-    let mut home_dir_pathbuf = get_home_dir()?;
-    home_dir_pathbuf.push(".scaii".to_string());
-    common::ensure_dir_exists(&home_dir_pathbuf)?;
-    Ok(home_dir_pathbuf)
-}
-
-fn get_default_install_dir() -> Result<PathBuf, Box<Error>> {
-    use platform::common;
+// the checkout belonging to the active version
+fn get_default_install_dir(dot_scaii: &PathBuf) -> Result<PathBuf, Box<Error>> {
+    use error::InstallError;
 
-    let mut install_dir_pathbuf = get_dot_scaii_dir()?;
-    install_dir_pathbuf.push("git".to_string());
-    common::ensure_dir_exists(&install_dir_pathbuf)?;
-    Ok(install_dir_pathbuf)
+    match versions::read_active(dot_scaii)? {
+        Some(name) => {
+            let mut install_dir_pathbuf = versions::get_version_dir(dot_scaii, &name)?;
+            install_dir_pathbuf.push("git".to_string());
+            Ok(install_dir_pathbuf)
+        }
+        None => Err(Box::new(InstallError::Prerequisite(
+            "no version is active - run 'install' or 'use' first.".to_string(),
+        ))),
+    }
 }
 
-fn build_core(install_dir: &PathBuf, compile_type: String) -> Result<(), Box<Error>> {
+fn build_core(
+    scaii_dir: &PathBuf,
+    dest_root: &PathBuf,
+    compile_type: String,
+    features: &Vec<String>,
+    manifest: &mut Manifest,
+) -> Result<(), Box<Error>> {
     use common;
     use error::InstallError;
+    use manifest::COMPONENT_CORE;
 
     println!("\nbuilding SCAII");
     println!("");
     let orig_dir_pathbuf = env::current_dir()?;
     //cd SCAII/
-    let mut scaii_install_dir = install_dir.clone();
-    scaii_install_dir.push("SCAII".to_string());
+    let scaii_install_dir = scaii_dir.clone();
     if !scaii_install_dir.as_path().exists() {
-        return Err(Box::new(InstallError::new(
+        return Err(Box::new(InstallError::Prerequisite(
             "scaii core has not been installed - run 'get-core' command first.".to_string(),
         )));
     }
@@ -320,51 +1231,77 @@ fn build_core(install_dir: &PathBuf, compile_type: String) -> Result<(), Box<Err
     env::set_current_dir(scaii_install_dir.as_path())?;
 
     //cargo build --release
-    let command: String = "cargo".to_string();
-    let mut args: Vec<String> = Vec::new();
-    args.push("build".to_string());
-    if compile_type != "" {
-        args.push(compile_type.clone());
-    }
-
-    let build_output = run_command(&command, args)?;
-    if build_output.contains("error") {
-        return Err(Box::new(InstallError::new(format!(
-            "ERROR - cargo build failed {:?}",
-            build_output
-        ))));
-    }
+    cargo_build(&compile_type, features)?;
 
     //mkdir ~/.scaii
     //mkdir ~/.scaii/bin
-    let mut bindir = get_dot_scaii_dir()?;
+    let mut bindir = dest_root.clone();
     bindir.push("bin");
     common::ensure_dir_exists(&bindir)?;
 
     //cp target/release/libscaii_core.so ~/.scaii/bin/
-    assert!(scaii_install_dir.ends_with("SCAII"));
     let mut source = scaii_install_dir.clone();
     source.push("target".to_string());
-    if compile_type != "" {
-        source.push("release".to_string());
-    } else {
-        source.push("debug".to_string());
-    }
+    source.push(versions::profile_name(&compile_type));
     let target = bindir.clone();
     copy_built_core(source, target)?;
+    manifest.record_tree(dest_root, &bindir, COMPONENT_CORE)?;
 
     //cp -r glue ~/.scaii/
     let mut source = scaii_install_dir.clone();
     source.push("glue".to_string());
-    let mut dest = get_dot_scaii_dir()?;
+    let mut dest = dest_root.clone();
     dest.push("glue".to_string());
     copy_recursive(source, &dest)?;
+    manifest.record_tree(dest_root, &dest, COMPONENT_CORE)?;
     env::set_current_dir(orig_dir_pathbuf.as_path())?;
     Ok(())
 }
 
-fn shallow_clean() -> Result<(), Box<Error>> {
-    let mut dir = get_dot_scaii_dir()?;
+// runs cargo build in the current directory, failing if cargo exits with an error
+fn cargo_build(compile_type: &String, features: &Vec<String>) -> Result<(), Box<Error>> {
+    use error::{ErrorKind, InstallError};
+
+    let command: String = "cargo".to_string();
+    let mut args: Vec<String> = Vec::new();
+    args.push("build".to_string());
+    if compile_type != "" {
+        args.push(compile_type.clone());
+    }
+    if !features.is_empty() {
+        args.push("--features".to_string());
+        args.push(features.join(" "));
+    }
+
+    // run_command passes cargo's output through to the console, so its exit status is
+    // what says whether the build worked
+    match run_command(&command, args) {
+        Ok(_) => Ok(()),
+        // a missing cargo stays a missing prerequisite
+        Err(err) => match error::error_kind(&*err) {
+            ErrorKind::Prerequisite => Err(err),
+            _ => Err(Box::new(
+                InstallError::build(
+                    &env::current_dir()?,
+                    "ERROR - cargo build failed".to_string(),
+                )
+                .caused_by(err),
+            )),
+        },
+    }
+}
+
+fn shallow_clean(version_dir: &PathBuf) -> Result<(), Box<Error>> {
+    let mut dir = version_dir.clone();
+    if let Some(mut manifest) = Manifest::load(&dir)? {
+        manifest.remove_files(&dir, None)?;
+        if manifest.files.is_empty() {
+            Manifest::delete(&dir)?;
+        } else {
+            manifest.save(&dir)?;
+        }
+        return Ok(());
+    }
 
     dir.push("backends".to_string());
     if dir.as_path().exists() {
@@ -389,33 +1326,26 @@ fn shallow_clean() -> Result<(), Box<Error>> {
     Ok(())
 }
 
-fn get_home_dir() -> Result<PathBuf, Box<Error>> {
-    use error::InstallError;
-
-    let result: Option<PathBuf> = env::home_dir();
-    match result {
-        Some(pathbuf) => Ok(pathbuf),
-        None => Err(Box::new(InstallError::new(
-            "could not determine user's home directory".to_string(),
-        ))),
-    }
-}
-
-fn build_sky_rts(install_dir: PathBuf) -> Result<(), Box<Error>> {
+fn build_sky_rts(
+    scaii_dir: PathBuf,
+    dest_root: &PathBuf,
+    compile_type: String,
+    manifest: &mut Manifest,
+) -> Result<(), Box<Error>> {
     use error::InstallError;
+    use manifest::COMPONENT_SKY_RTS;
     use platform::common;
-    println!("building SCAII...");
-    let mut sky_rts_dir = install_dir;
-    sky_rts_dir.push("SCAII");
+    println!("building Sky-RTS...");
+    let sky_rts_dir = scaii_dir;
     if !sky_rts_dir.as_path().exists() {
-        return Err(Box::new(InstallError::new(
+        return Err(Box::new(InstallError::Prerequisite(
             "Sky-RTS has not been installed - run 'get-sky-rts' command first.".to_string(),
         )));
     }
 
     let orig_dir_pathbuf = env::current_dir()?;
     //mkdir ~/.scaii/backends
-    let mut dir = get_dot_scaii_dir()?;
+    let mut dir = dest_root.clone();
     common::ensure_dir_exists(&dir)?;
     dir.push("backends".to_string());
     common::ensure_dir_exists(&dir)?;
@@ -462,12 +1392,13 @@ fn build_sky_rts(install_dir: PathBuf) -> Result<(), Box<Error>> {
 
     source.push(".".to_string());
 
-    let mut dest = get_dot_scaii_dir()?;
+    let mut dest = dest_root.clone();
     dest.push("glue");
     dest.push("python");
     dest.push("scaii");
     dest.push("env");
     copy_recursive(source, &dest)?;
+    manifest.record_tree(dest_root, &dest, COMPONENT_SKY_RTS)?;
 
     // cp backend/lua/* ~/.scaii/backends/sky-rts/maps
     let mut source = backend.clone();
@@ -476,12 +1407,39 @@ fn build_sky_rts(install_dir: PathBuf) -> Result<(), Box<Error>> {
 
     source.push(".".to_string());
 
-    let mut dest = get_dot_scaii_dir()?;
+    let mut dest = dest_root.clone();
     dest.push("backends".to_string());
     dest.push("sky-rts".to_string());
     dest.push("maps".to_string());
 
     copy_recursive(source, &dest)?;
+    manifest.record_tree(dest_root, &dest, COMPONENT_SKY_RTS)?;
+
+    // cd sky-rts/backend
+    // cargo build --release
+    let mut backend_crate = backend.clone();
+    backend_crate.push("sky-rts".to_string());
+    backend_crate.push("backend".to_string());
+    env::set_current_dir(backend_crate.as_path())?;
+    cargo_build(&compile_type, &Vec::new())?;
+
+    // the backend is built into the SCAII workspace target dir when it is a
+    // workspace member, otherwise into its own
+    let mut source = backend_crate.clone();
+    source.push("target".to_string());
+    source.push(versions::profile_name(&compile_type));
+    if !source.as_path().exists() {
+        source = sky_rts_dir.clone();
+        source.push("target".to_string());
+        source.push(versions::profile_name(&compile_type));
+    }
+
+    // cp target/release/libbackend.so ~/.scaii/backends/bin/libsky-rts.so
+    let mut dest = dest_root.clone();
+    dest.push("backends".to_string());
+    dest.push("bin".to_string());
+    copy_built_rts(source, dest.clone())?;
+    manifest.record_tree(dest_root, &dest, COMPONENT_SKY_RTS)?;
 
     // export PYTHONPATH=$PYTHONPATH:/home/lamki/.scaii/bin:/home/lamki/.scaii/glue/python/
     // export LD_LIBRARY_PATH=$LD_LIBRARY_PATH:/home/lamki/.scaii/bin/
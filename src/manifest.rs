@@ -0,0 +1,244 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use platform::common;
+
+// each version dir, ~/.scaii/versions/<name>, has a manifest.toml listing every file the
+// install wrote into it, so that cleaning removes exactly those files and nothing else.
+pub const MANIFEST_FILENAME: &'static str = "manifest.toml";
+
+pub const COMPONENT_CORE: &'static str = "core";
+pub const COMPONENT_SKY_RTS: &'static str = "sky-rts";
+pub const COMPONENT_EXECS: &'static str = "execs";
+pub const COMPONENT_EXTRA_BACKENDS: &'static str = "extra-backends";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub commit: String,
+    #[serde(default)]
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    // relative to ~/.scaii, always using '/' as the separator
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+    pub component: String,
+    pub commit: String,
+}
+
+impl Manifest {
+    pub fn new(commit: String) -> Manifest {
+        Manifest {
+            commit: commit,
+            files: Vec::new(),
+        }
+    }
+
+    // returns None for installs made before manifests were written
+    pub fn load(dot_scaii: &PathBuf) -> Result<Option<Manifest>, Box<Error>> {
+        use std::io::Read;
+        use toml;
+
+        let path = dot_scaii.join(MANIFEST_FILENAME);
+        if !path.as_path().exists() {
+            return Ok(None);
+        }
+        let mut contents = String::new();
+        fs::File::open(&path)?.read_to_string(&mut contents)?;
+        let manifest: Manifest = toml::from_str(&contents)?;
+        Ok(Some(manifest))
+    }
+
+    pub fn save(&self, dot_scaii: &PathBuf) -> Result<(), Box<Error>> {
+        use std::io::Write;
+        use toml;
+
+        let contents = toml::to_string(self)?;
+        let path = dot_scaii.join(MANIFEST_FILENAME);
+        let mut file = fs::File::create(&path)?;
+        file.write_all(contents.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn delete(dot_scaii: &PathBuf) -> Result<(), Box<Error>> {
+        let path = dot_scaii.join(MANIFEST_FILENAME);
+        if path.as_path().exists() {
+            fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+
+    // records every file at or below path.  Files already claimed by an earlier
+    // component (e.g. core glue that sky-rts glue was copied on top of) keep their owner.
+    pub fn record_tree(
+        &mut self,
+        dot_scaii: &PathBuf,
+        path: &PathBuf,
+        component: &str,
+    ) -> Result<(), Box<Error>> {
+        let mut files: Vec<PathBuf> = Vec::new();
+        collect_files(path, &mut files)?;
+        for file in files {
+            if self.contains(dot_scaii, &file) {
+                continue;
+            }
+            let relative = relative_path_string(dot_scaii, &file)?;
+            let entry = ManifestEntry {
+                path: relative,
+                size: fs::metadata(&file)?.len(),
+                sha256: common::sha256_file(&file)?,
+                component: component.to_string(),
+                commit: self.commit.clone(),
+            };
+            self.files.push(entry);
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, dot_scaii: &PathBuf, file: &PathBuf) -> bool {
+        match relative_path_string(dot_scaii, file) {
+            Ok(relative) => self.files.iter().any(|entry| entry.path == relative),
+            Err(_) => false,
+        }
+    }
+
+    // removes the files listed for component (or every file if component is None) and
+    // drops them from the manifest.  Files whose contents no longer match the recorded
+    // hash were modified by the user, so they are left in place.
+    pub fn remove_files(
+        &mut self,
+        dot_scaii: &PathBuf,
+        component: Option<&str>,
+    ) -> Result<(), Box<Error>> {
+        let mut kept: Vec<ManifestEntry> = Vec::new();
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        for entry in self.files.drain(..) {
+            let selected = match component {
+                Some(name) => entry.component == name,
+                None => true,
+            };
+            if !selected {
+                kept.push(entry);
+                continue;
+            }
+            let path = append_manifest_path(dot_scaii, &entry.path);
+            if path.as_path().exists() {
+                if common::sha256_file(&path)? != entry.sha256 {
+                    println!("leaving modified file in place {:?}", path);
+                    continue;
+                }
+                fs::remove_file(&path)?;
+            }
+            if let Some(parent) = path.parent() {
+                dirs.push(parent.to_path_buf());
+            }
+        }
+        self.files = kept;
+        remove_empty_dirs(dot_scaii, dirs)?;
+        Ok(())
+    }
+
+    // checks the files recorded at or below path (relative, using '/'), returning how
+    // many are recorded, how many of those are missing, and how many were modified
+    pub fn check_files(
+        &self,
+        dot_scaii: &PathBuf,
+        path: &str,
+    ) -> Result<(usize, usize, usize), Box<Error>> {
+        let prefix = format!("{}/", path);
+        let mut recorded = 0;
+        let mut missing = 0;
+        let mut modified = 0;
+        for entry in self.files.iter() {
+            if entry.path != path && !entry.path.starts_with(&prefix) {
+                continue;
+            }
+            recorded += 1;
+            let file = append_manifest_path(dot_scaii, &entry.path);
+            let metadata = match fs::metadata(&file) {
+                Ok(metadata) => metadata,
+                Err(_) => {
+                    missing += 1;
+                    continue;
+                }
+            };
+            // a different size needs no hashing to know it changed
+            if metadata.len() != entry.size || common::sha256_file(&file)? != entry.sha256 {
+                modified += 1;
+            }
+        }
+        Ok((recorded, missing, modified))
+    }
+}
+
+fn collect_files(path: &PathBuf, files: &mut Vec<PathBuf>) -> Result<(), Box<Error>> {
+    if !path.as_path().exists() {
+        return Ok(());
+    }
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            collect_files(&entry?.path(), files)?;
+        }
+    } else {
+        files.push(path.clone());
+    }
+    Ok(())
+}
+
+fn relative_path_string(dot_scaii: &PathBuf, file: &PathBuf) -> Result<String, Box<Error>> {
+    use error::InstallError;
+
+    match file.strip_prefix(dot_scaii) {
+        Ok(relative) => {
+            let parts: Vec<String> = relative
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy().to_string())
+                .collect();
+            Ok(parts.join("/"))
+        }
+        Err(_) => Err(Box::new(InstallError::new(format!(
+            "ERROR - {:?} is not inside {:?} and cannot be recorded in the manifest",
+            file, dot_scaii
+        )))),
+    }
+}
+
+fn append_manifest_path(dot_scaii: &PathBuf, relative: &str) -> PathBuf {
+    let mut path = dot_scaii.clone();
+    for part in relative.split("/") {
+        path.push(part);
+    }
+    path
+}
+
+// walks up from each directory that had files removed, deleting directories left empty,
+// stopping at ~/.scaii itself
+fn remove_empty_dirs(dot_scaii: &PathBuf, mut dirs: Vec<PathBuf>) -> Result<(), Box<Error>> {
+    // deepest first so children are removed before their parents are checked
+    dirs.sort_by(|a, b| {
+        b.components()
+            .count()
+            .cmp(&a.components().count())
+            .then(a.cmp(b))
+    });
+    dirs.dedup();
+    for dir in dirs {
+        let mut current: &Path = dir.as_path();
+        while current != dot_scaii.as_path() && current.starts_with(dot_scaii) {
+            if !current.exists() || fs::read_dir(current)?.next().is_some() {
+                break;
+            }
+            fs::remove_dir(current)?;
+            current = match current.parent() {
+                Some(parent) => parent,
+                None => break,
+            };
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,297 @@
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use config::Config;
+use error::InstallError;
+
+// Downloads are streamed into <target>.part and renamed into place once complete.  A
+// .part file left by an interrupted download is resumed with an HTTP range request, and
+// failed attempts are retried with a doubling delay.  Only successful responses are
+// written to the .part file, and it is removed once the download has finally failed.
+const DEFAULT_RETRIES: u32 = 3;
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const FIRST_RETRY_DELAY_SECS: u64 = 2;
+const PROGRESS_BAR_WIDTH: usize = 40;
+
+struct Download {
+    file: File,
+    // bytes already in the .part file when this attempt started
+    resumed_from: u64,
+    // the status of the last response seen, which follows redirects
+    status: u32,
+    started_writing: bool,
+    write_error: Option<io::Error>,
+    last_line: Option<String>,
+}
+
+impl Download {
+    fn show_progress(&mut self, total: Option<u64>, now: u64) {
+        use std::io::Write;
+
+        let line = progress_line(total, now);
+        if self.last_line.as_ref() == Some(&line) {
+            return;
+        }
+        print!("\r{}", line);
+        let _ = io::stdout().flush();
+        self.last_line = Some(line);
+    }
+}
+
+// a bar for now of total bytes, or just the bytes when the server didn't say how many
+// it is sending.  The bar stops at full in case more arrive than total.
+fn progress_line(total: Option<u64>, now: u64) -> String {
+    use std::cmp;
+
+    let total = match total {
+        Some(total) if total > 0 => total,
+        _ => return format!("{:.1} MiB", now as f64 / 1048576.0),
+    };
+    let percent = cmp::min(now * 100 / total, 100);
+    let filled = cmp::min(
+        percent as usize * PROGRESS_BAR_WIDTH / 100,
+        PROGRESS_BAR_WIDTH,
+    );
+    format!(
+        "[{}{}] {:3}% {:.1}/{:.1} MiB",
+        "#".repeat(filled),
+        " ".repeat(PROGRESS_BAR_WIDTH - filled),
+        percent,
+        now as f64 / 1048576.0,
+        total as f64 / 1048576.0
+    )
+}
+
+impl ::curl::easy::Handler for Download {
+    fn header(&mut self, data: &[u8]) -> bool {
+        let line = String::from_utf8_lossy(data);
+        if line.starts_with("HTTP/") {
+            if let Some(code) = line.split_whitespace().nth(1) {
+                self.status = code.parse().unwrap_or(0);
+            }
+        }
+        true
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, ::curl::easy::WriteError> {
+        use std::io::Write;
+
+        // the body of an error page is not the file, so it is dropped rather than left in
+        // the .part file for a retry or the next mirror to resume from
+        if self.status != 0 && (self.status < 200 || self.status >= 300) {
+            return Ok(data.len());
+        }
+        if !self.started_writing {
+            self.started_writing = true;
+            // a server that ignores the range request sends the whole file again.  Only
+            // http responses have a status; file:// transfers honor the range.
+            if self.resumed_from > 0 && self.status != 0 && self.status != 206 {
+                if let Err(err) = self.file.set_len(0) {
+                    self.write_error = Some(err);
+                    return Ok(0);
+                }
+                self.resumed_from = 0;
+            }
+        }
+        match self.file.write_all(data) {
+            Ok(()) => Ok(data.len()),
+            Err(err) => {
+                // returning a short count makes curl abort the transfer
+                self.write_error = Some(err);
+                Ok(0)
+            }
+        }
+    }
+
+    fn progress(&mut self, dltotal: f64, dlnow: f64, _ultotal: f64, _ulnow: f64) -> bool {
+        let offset = self.resumed_from;
+        // a chunked response has no Content-Length, so curl reports no total
+        let total = if dltotal > 0.0 {
+            Some(offset + dltotal as u64)
+        } else {
+            None
+        };
+        if total.is_some() || dlnow > 0.0 {
+            self.show_progress(total, offset + dlnow as u64);
+        }
+        true
+    }
+}
+
+pub fn download_using_curl(
+    url: &String,
+    target_path: &PathBuf,
+    config: &Config,
+) -> Result<(), Box<Error>> {
+    use std::fs;
+    use std::thread;
+
+    let retries = config.download_retries.unwrap_or(DEFAULT_RETRIES);
+    let part_path = part_path(target_path);
+
+    let mut delay = Duration::from_secs(FIRST_RETRY_DELAY_SECS);
+    let mut attempt = 0;
+    loop {
+        match download_attempt(url, &part_path, config) {
+            Ok(()) => break,
+            Err((err, retryable)) => {
+                if !retryable || attempt >= retries {
+                    discard_partial(target_path);
+                    return Err(err);
+                }
+                attempt += 1;
+                println!(
+                    "download of {} failed ({}), retrying in {}s ({} of {})...",
+                    url,
+                    err,
+                    delay.as_secs(),
+                    attempt,
+                    retries
+                );
+                thread::sleep(delay);
+                delay = delay * 2;
+            }
+        }
+    }
+    fs::rename(&part_path, target_path)?;
+    Ok(())
+}
+
+// removes what a failed download of target_path left behind, so that nothing fetched
+// from one url is resumed from another
+pub fn discard_partial(target_path: &PathBuf) {
+    use std::fs;
+
+    let part_path = part_path(target_path);
+    if part_path.as_path().exists() {
+        if let Err(err) = fs::remove_file(&part_path) {
+            println!("could not remove {:?}: {}", part_path, err);
+        }
+    }
+}
+
+fn part_path(target_path: &PathBuf) -> PathBuf {
+    let mut part_path = target_path.clone().into_os_string();
+    part_path.push(".part");
+    PathBuf::from(part_path)
+}
+
+// one try at the download, resuming whatever an earlier try left in part_path.  Errors
+// come back with whether trying again could help.
+fn download_attempt(
+    url: &String,
+    part_path: &PathBuf,
+    config: &Config,
+) -> Result<(), (Box<Error>, bool)> {
+    use curl::easy::Easy2;
+    use std::fs::OpenOptions;
+
+    let timeout = Duration::from_secs(config.download_timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(part_path)
+        .map_err(|err| {
+            let err =
+                InstallError::filesystem(part_path, "could not open the download".to_string())
+                    .caused_by(Box::new(err));
+            (Box::new(err) as Box<Error>, false)
+        })?;
+    let resumed_from = file
+        .metadata()
+        .map_err(|err| (Box::new(err) as Box<Error>, false))?
+        .len();
+    if resumed_from > 0 {
+        println!("resuming download at {} bytes", resumed_from);
+    }
+
+    let mut easy = Easy2::new(Download {
+        file: file,
+        resumed_from: resumed_from,
+        status: 0,
+        started_writing: false,
+        write_error: None,
+        last_line: None,
+    });
+    // a mirror may be a plain path to a local copy
+    let curl_url = if url.contains("://") {
+        url.clone()
+    } else {
+        format!("file://{}", url)
+    };
+    let setup = easy
+        .get(true)
+        .and_then(|_| easy.follow_location(true))
+        .and_then(|_| easy.url(&curl_url))
+        .and_then(|_| easy.progress(true))
+        .and_then(|_| easy.connect_timeout(timeout))
+        // give up on a connection that stalls below 1 byte/s for the whole timeout
+        .and_then(|_| easy.low_speed_limit(1))
+        .and_then(|_| easy.low_speed_time(timeout))
+        .and_then(|_| easy.resume_from(resumed_from));
+    if let Err(err) = setup {
+        return Err((Box::new(err), false));
+    }
+
+    let result = easy.perform();
+    println!("");
+    if let Some(err) = easy.get_mut().write_error.take() {
+        return Err((Box::new(err), false));
+    }
+    if let Err(err) = result {
+        let err =
+            InstallError::network(url, "the transfer failed".to_string()).caused_by(Box::new(err));
+        return Err((Box::new(err), true));
+    }
+    let status = easy
+        .response_code()
+        .map_err(|err| (Box::new(err) as Box<Error>, true))?;
+    match status {
+        // file:// transfers have no response code
+        0 | 200 | 206 => Ok(()),
+        // the range asked for starts past the end of the file: start over
+        416 => {
+            easy.get_mut()
+                .file
+                .set_len(0)
+                .map_err(|err| (Box::new(err) as Box<Error>, false))?;
+            Err((http_error(url, status), true))
+        }
+        // only the server's own failures and throttling are worth retrying
+        408 | 429 => Err((http_error(url, status), true)),
+        code if code >= 500 => Err((http_error(url, status), true)),
+        _ => Err((http_error(url, status), false)),
+    }
+}
+
+fn http_error(url: &String, status: u32) -> Box<Error> {
+    Box::new(InstallError::network(
+        url,
+        format!("server returned HTTP {}", status),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_without_a_total_shows_only_bytes() {
+        assert_eq!(progress_line(None, 3 * 1048576), "3.0 MiB");
+        assert_eq!(progress_line(Some(0), 1048576), "1.0 MiB");
+    }
+
+    #[test]
+    fn progress_bar_stays_within_its_width() {
+        let half = progress_line(Some(4 * 1048576), 2 * 1048576);
+        assert!(half.starts_with(&format!("[{}{}]", "#".repeat(20), " ".repeat(20))));
+        assert!(half.ends_with(" 50% 2.0/4.0 MiB"));
+        // a resumed download whose total was taken from the .part file alone
+        let over = progress_line(Some(1048576), 5 * 1048576);
+        assert!(over.starts_with(&format!("[{}]", "#".repeat(PROGRESS_BAR_WIDTH))));
+        assert!(over.contains("100%"));
+    }
+}
@@ -0,0 +1,827 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use error::InstallError;
+
+// Downloaded archives aren't trusted to stay inside the directory they are extracted
+// into.  An entry whose name is absolute or climbs out with '..' fails the whole
+// extraction, nothing is written through a symlink, and symlinks are only created - once
+// everything else is in place - when what they point at is inside the directory too.
+// Permission bits recorded in the archive are kept, apart from setuid, setgid and sticky.
+const MODE_MASK: u32 = 0o777;
+const FILE_TYPE_MASK: u32 = 0o170000;
+const FILE_TYPE_DIR: u32 = 0o040000;
+const FILE_TYPE_SYMLINK: u32 = 0o120000;
+
+// a symlink in the archive, created after the files and directories
+struct Link {
+    name: String,
+    relative: PathBuf,
+    target: String,
+}
+
+// what an archive is, told from its first bytes rather than its name since a url
+// doesn't always end in one
+#[derive(Debug, PartialEq)]
+enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+}
+
+// Extracts the zip, tar, tar.gz or tar.xz archive at archive_path into dest_dir and returns
+// how many entries were extracted.  The first strip_components directories of each
+// entry's name are dropped, as tar's --strip-components does, so the top-level directory a
+// release archive wraps everything in doesn't need to be known.  Then only the entries
+// under archive_dir ("" for all of them, else a directory like "js/") are extracted,
+// without archive_dir in their paths.
+pub fn extract_archive(
+    archive_path: &PathBuf,
+    dest_dir: &PathBuf,
+    strip_components: usize,
+    archive_dir: &str,
+) -> Result<usize, Box<Error>> {
+    use super::ensure_dir_exists;
+    use flate2::read::GzDecoder;
+    use xz2::read::XzDecoder;
+
+    let format = archive_format(archive_path)?;
+    println!("extracting {:?} ({:?})", archive_path, format);
+    ensure_dir_exists(dest_dir)?;
+    let mut links = Vec::new();
+    let mut dir_modes = Vec::new();
+    let extracted = match format {
+        ArchiveFormat::Zip => extract_zip(
+            archive_path,
+            dest_dir,
+            strip_components,
+            archive_dir,
+            &mut links,
+            &mut dir_modes,
+        )?,
+        ArchiveFormat::Tar => {
+            let file = File::open(archive_path)?;
+            extract_tar(
+                file,
+                archive_path,
+                dest_dir,
+                strip_components,
+                archive_dir,
+                &mut links,
+                &mut dir_modes,
+            )?
+        }
+        ArchiveFormat::TarGz => {
+            let file = File::open(archive_path)?;
+            extract_tar(
+                GzDecoder::new(file),
+                archive_path,
+                dest_dir,
+                strip_components,
+                archive_dir,
+                &mut links,
+                &mut dir_modes,
+            )?
+        }
+        ArchiveFormat::TarXz => {
+            let file = File::open(archive_path)?;
+            extract_tar(
+                XzDecoder::new(file),
+                archive_path,
+                dest_dir,
+                strip_components,
+                archive_dir,
+                &mut links,
+                &mut dir_modes,
+            )?
+        }
+    };
+    create_links(archive_path, dest_dir, &links)?;
+    // applied last so that a directory without write permission could still be filled
+    for &(ref dir, mode) in dir_modes.iter() {
+        set_mode(dir, Some(mode | 0o700))?;
+    }
+    Ok(extracted)
+}
+
+fn archive_format(archive_path: &PathBuf) -> Result<ArchiveFormat, Box<Error>> {
+    let mut file = File::open(archive_path)?;
+    // a tar has "ustar" in its header, after the 257 bytes of name, mode and so on
+    let mut start = Vec::new();
+    file.by_ref().take(262).read_to_end(&mut start)?;
+    if start.starts_with(b"PK\x03\x04") || start.starts_with(b"PK\x05\x06") {
+        Ok(ArchiveFormat::Zip)
+    } else if start.starts_with(b"\x1f\x8b") {
+        Ok(ArchiveFormat::TarGz)
+    } else if start.starts_with(b"\xfd7zXZ\x00") {
+        Ok(ArchiveFormat::TarXz)
+    } else if start.len() == 262 && &start[257..] == b"ustar" {
+        Ok(ArchiveFormat::Tar)
+    } else {
+        Err(Box::new(InstallError::archive(
+            archive_path,
+            "not a zip, tar, tar.gz or tar.xz archive".to_string(),
+        )))
+    }
+}
+
+fn extract_zip(
+    archive_path: &PathBuf,
+    dest_dir: &PathBuf,
+    strip_components: usize,
+    archive_dir: &str,
+    links: &mut Vec<Link>,
+    dir_modes: &mut Vec<(PathBuf, u32)>,
+) -> Result<usize, Box<Error>> {
+    use zip::ZipArchive;
+
+    let file = File::open(archive_path)?;
+    let mut zip = ZipArchive::new(file).map_err(|err| {
+        InstallError::archive(archive_path, "not a readable zip archive".to_string())
+            .caused_by(Box::new(err))
+    })?;
+    let mut extracted = 0;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        let name = entry.name().to_string();
+        let relative = match entry_path(archive_path, &name, strip_components, archive_dir)? {
+            Some(relative) => relative,
+            None => continue,
+        };
+        // zips made on unix record the mode, including the file type, in the top bits
+        // of the external attributes; a zero mode means none was recorded
+        let mode = entry.unix_mode().filter(|mode| *mode & MODE_MASK != 0);
+        let file_type = mode.map(|mode| mode & FILE_TYPE_MASK);
+        extracted += 1;
+        if name.ends_with('/') || name.ends_with('\\') || file_type == Some(FILE_TYPE_DIR) {
+            create_dirs(archive_path, dest_dir, &relative)?;
+            if let Some(mode) = mode {
+                dir_modes.push((dest_dir.join(&relative), mode));
+            }
+        } else if file_type == Some(FILE_TYPE_SYMLINK) {
+            let mut target = String::new();
+            entry.read_to_string(&mut target)?;
+            links.push(Link {
+                name: name,
+                relative: relative,
+                target: target,
+            });
+        } else {
+            write_file(archive_path, dest_dir, &relative, &mut entry, mode)?;
+        }
+    }
+    Ok(extracted)
+}
+
+fn extract_tar<R: Read>(
+    reader: R,
+    archive_path: &PathBuf,
+    dest_dir: &PathBuf,
+    strip_components: usize,
+    archive_dir: &str,
+    links: &mut Vec<Link>,
+    dir_modes: &mut Vec<(PathBuf, u32)>,
+) -> Result<usize, Box<Error>> {
+    use tar::Archive;
+
+    let unreadable = |err| {
+        Box::new(
+            InstallError::archive(archive_path, "not a readable tar archive".to_string())
+                .caused_by(Box::new(err)),
+        ) as Box<Error>
+    };
+    let mut archive = Archive::new(reader);
+    let mut extracted = 0;
+    for entry in archive.entries().map_err(&unreadable)? {
+        let mut entry = entry.map_err(&unreadable)?;
+        let name = String::from_utf8_lossy(&entry.path_bytes()).to_string();
+        let relative = match entry_path(archive_path, &name, strip_components, archive_dir)? {
+            Some(relative) => relative,
+            None => continue,
+        };
+        let entry_type = entry.header().entry_type();
+        let mode = entry
+            .header()
+            .mode()
+            .ok()
+            .filter(|mode| *mode & MODE_MASK != 0);
+        if entry_type.is_dir() {
+            create_dirs(archive_path, dest_dir, &relative)?;
+            if let Some(mode) = mode {
+                dir_modes.push((dest_dir.join(&relative), mode));
+            }
+        } else if entry_type.is_symlink() {
+            let target = match entry.link_name_bytes() {
+                Some(target) => String::from_utf8_lossy(&target).to_string(),
+                None => continue,
+            };
+            links.push(Link {
+                name: name,
+                relative: relative,
+                target: target,
+            });
+        } else if entry_type.is_file() || entry_type.is_contiguous() {
+            write_file(archive_path, dest_dir, &relative, &mut entry, mode)?;
+        } else {
+            // hard links, devices, fifos and the pax global header github's archives start
+            // with aren't needed by anything installed
+            continue;
+        }
+        extracted += 1;
+    }
+    Ok(extracted)
+}
+
+// the path of the entry named name relative to the directory it is extracted into, or
+// None if it isn't to be extracted
+fn entry_path(
+    archive_path: &PathBuf,
+    name: &str,
+    strip_components: usize,
+    archive_dir: &str,
+) -> Result<Option<PathBuf>, Box<Error>> {
+    let name = name.replace('\\', "/");
+    if name.starts_with('/') {
+        return Err(unsafe_entry(archive_path, &name, "is an absolute path"));
+    }
+    let mut parts = Vec::new();
+    for part in name.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                return Err(unsafe_entry(
+                    archive_path,
+                    &name,
+                    "climbs out of the directory it is extracted into",
+                ))
+            }
+            // a drive like C: or an alternate data stream, on windows
+            part if cfg!(windows) && part.contains(':') => {
+                return Err(unsafe_entry(archive_path, &name, "names a drive or stream"))
+            }
+            part => parts.push(part),
+        }
+    }
+    if parts.len() <= strip_components {
+        return Ok(None);
+    }
+    let parts = &parts[strip_components..];
+    let archive_dir: Vec<&str> = archive_dir.split('/').filter(|part| *part != "").collect();
+    if parts.len() <= archive_dir.len() || parts[..archive_dir.len()] != archive_dir[..] {
+        return Ok(None);
+    }
+    let mut relative = PathBuf::new();
+    for part in parts[archive_dir.len()..].iter() {
+        relative.push(part);
+    }
+    Ok(Some(relative))
+}
+
+// creates each directory of relative under dest_dir, refusing to go through a symlink
+fn create_dirs(
+    archive_path: &PathBuf,
+    dest_dir: &PathBuf,
+    relative: &Path,
+) -> Result<(), Box<Error>> {
+    let mut path = dest_dir.clone();
+    for part in relative.iter() {
+        path.push(part);
+        if is_symlink(&path) {
+            return Err(unsafe_entry(
+                archive_path,
+                &relative.to_string_lossy(),
+                "would be extracted through a symlink",
+            ));
+        }
+        if !path.as_path().exists() {
+            fs::create_dir(&path).map_err(|err| {
+                InstallError::filesystem(&path, "could not create directory".to_string())
+                    .caused_by(Box::new(err))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn write_file(
+    archive_path: &PathBuf,
+    dest_dir: &PathBuf,
+    relative: &PathBuf,
+    contents: &mut Read,
+    mode: Option<u32>,
+) -> Result<(), Box<Error>> {
+    use std::io;
+
+    if let Some(parent) = relative.parent() {
+        create_dirs(archive_path, dest_dir, parent)?;
+    }
+    let path = dest_dir.join(relative);
+    // a symlink left by an earlier install is replaced rather than written through
+    if is_symlink(&path) {
+        fs::remove_file(&path)?;
+    }
+    let mut file = File::create(&path).map_err(|err| {
+        InstallError::filesystem(&path, "could not create file".to_string())
+            .caused_by(Box::new(err))
+    })?;
+    io::copy(contents, &mut file)?;
+    set_mode(&path, mode)
+}
+
+fn create_links(
+    archive_path: &PathBuf,
+    dest_dir: &PathBuf,
+    links: &Vec<Link>,
+) -> Result<(), Box<Error>> {
+    for link in links.iter() {
+        check_link_target(archive_path, link)?;
+        if let Some(parent) = link.relative.parent() {
+            create_dirs(archive_path, dest_dir, parent)?;
+        }
+        let path = dest_dir.join(&link.relative);
+        if is_symlink(&path) || path.as_path().is_file() {
+            fs::remove_file(&path)?;
+        }
+        make_symlink(&link.target, &path)?;
+    }
+    Ok(())
+}
+
+// fails unless the link's target is relative and stays inside the directory the archive
+// is extracted into.  '..' may only lead the target, and only as far up as the link is
+// deep, since after a symlink it would climb out of wherever that symlink points.
+fn check_link_target(archive_path: &PathBuf, link: &Link) -> Result<(), Box<Error>> {
+    let target = link.target.replace('\\', "/");
+    if target.starts_with('/') || (cfg!(windows) && target.contains(':')) {
+        return Err(unsafe_entry(
+            archive_path,
+            &link.name,
+            &format!("is a symlink to {}, outside the archive", link.target),
+        ));
+    }
+    let mut depth = link.relative.iter().count() - 1;
+    let mut descended = false;
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." if !descended && depth > 0 => depth -= 1,
+            ".." => {
+                return Err(unsafe_entry(
+                    archive_path,
+                    &link.name,
+                    &format!("is a symlink to {}, which leaves the archive", link.target),
+                ))
+            }
+            _ => descended = true,
+        }
+    }
+    Ok(())
+}
+
+fn is_symlink(path: &PathBuf) -> bool {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata.file_type().is_symlink(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(unix)]
+fn make_symlink(target: &str, path: &PathBuf) -> Result<(), Box<Error>> {
+    use std::os::unix::fs::symlink;
+
+    symlink(target, path).map_err(|err| {
+        Box::new(
+            InstallError::filesystem(path, format!("could not create symlink to {}", target))
+                .caused_by(Box::new(err)),
+        ) as Box<Error>
+    })
+}
+
+// creating symlinks on windows needs privileges the installer usually doesn't have
+#[cfg(not(unix))]
+fn make_symlink(target: &str, path: &PathBuf) -> Result<(), Box<Error>> {
+    println!("...skipping symlink {:?} to {}", path, target);
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(path: &PathBuf, mode: Option<u32>) -> Result<(), Box<Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Some(mode) = mode {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode & MODE_MASK))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &PathBuf, _mode: Option<u32>) -> Result<(), Box<Error>> {
+    Ok(())
+}
+
+fn unsafe_entry(archive_path: &PathBuf, name: &str, problem: &str) -> Box<Error> {
+    Box::new(InstallError::archive(
+        archive_path,
+        format!("refusing to extract {} - it {}", name, problem),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+    use tar::EntryType;
+
+    const FILE: u32 = 0o100000;
+    const DIR: u32 = 0o040000;
+    const SYMLINK: u32 = 0o120000;
+
+    // an empty dir of its own for each test
+    fn scratch_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("sky-install-{}-{}", process::id(), test));
+        if dir.as_path().exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_archive(dir: &PathBuf, name: &str, bytes: &[u8]) -> PathBuf {
+        use std::io::Write;
+
+        let path = dir.join(name);
+        File::create(&path).unwrap().write_all(bytes).unwrap();
+        path
+    }
+
+    fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+        bytes.push(value as u8);
+        bytes.push((value >> 8) as u8);
+    }
+
+    fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+        push_u16(bytes, value as u16);
+        push_u16(bytes, (value >> 16) as u16);
+    }
+
+    // a zip of stored entries as made on unix, each (name, mode including the file type,
+    // contents), which the zip crate can't write for symlinks or names that climb out
+    fn zip_bytes(entries: &[(&str, u32, &[u8])]) -> Vec<u8> {
+        use flate2::Crc;
+
+        let mut bytes = Vec::new();
+        let mut central = Vec::new();
+        for &(name, mode, contents) in entries.iter() {
+            let mut crc = Crc::new();
+            crc.update(contents);
+            let offset = bytes.len() as u32;
+            push_u32(&mut bytes, 0x04034b50);
+            push_u16(&mut bytes, 20);
+            push_u16(&mut bytes, 0);
+            push_u16(&mut bytes, 0);
+            push_u16(&mut bytes, 0);
+            push_u16(&mut bytes, 0x21);
+            push_u32(&mut bytes, crc.sum());
+            push_u32(&mut bytes, contents.len() as u32);
+            push_u32(&mut bytes, contents.len() as u32);
+            push_u16(&mut bytes, name.len() as u16);
+            push_u16(&mut bytes, 0);
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(contents);
+
+            push_u32(&mut central, 0x02014b50);
+            // made by unix, so the mode is read from the external attributes
+            push_u16(&mut central, (3 << 8) | 20);
+            push_u16(&mut central, 20);
+            push_u16(&mut central, 0);
+            push_u16(&mut central, 0);
+            push_u16(&mut central, 0);
+            push_u16(&mut central, 0x21);
+            push_u32(&mut central, crc.sum());
+            push_u32(&mut central, contents.len() as u32);
+            push_u32(&mut central, contents.len() as u32);
+            push_u16(&mut central, name.len() as u16);
+            push_u16(&mut central, 0);
+            push_u16(&mut central, 0);
+            push_u16(&mut central, 0);
+            push_u16(&mut central, 0);
+            push_u32(&mut central, mode << 16);
+            push_u32(&mut central, offset);
+            central.extend_from_slice(name.as_bytes());
+        }
+        let central_offset = bytes.len() as u32;
+        bytes.extend_from_slice(&central);
+        push_u32(&mut bytes, 0x06054b50);
+        push_u16(&mut bytes, 0);
+        push_u16(&mut bytes, 0);
+        push_u16(&mut bytes, entries.len() as u16);
+        push_u16(&mut bytes, entries.len() as u16);
+        push_u32(&mut bytes, central.len() as u32);
+        push_u32(&mut bytes, central_offset);
+        push_u16(&mut bytes, 0);
+        bytes
+    }
+
+    // a tar of (name, type, mode, contents or link target) entries, with the names
+    // written as given rather than checked as tar::Builder would
+    fn tar_bytes(entries: &[(&str, EntryType, u32, &[u8])]) -> Vec<u8> {
+        use tar::{Builder, Header};
+
+        let mut builder = Builder::new(Vec::new());
+        for &(name, entry_type, mode, contents) in entries.iter() {
+            let mut header = Header::new_ustar();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(entry_type);
+            header.set_mode(mode);
+            let data = if entry_type.is_symlink() || entry_type.is_hard_link() {
+                header.as_old_mut().linkname[..contents.len()].copy_from_slice(contents);
+                &[][..]
+            } else {
+                contents
+            };
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append(&header, data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn xz(bytes: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        use xz2::write::XzEncoder;
+
+        let mut encoder = XzEncoder::new(Vec::new(), 6);
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn read(path: &PathBuf) -> String {
+        let mut contents = String::new();
+        File::open(path)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    fn assert_refused(result: Result<usize, Box<Error>>) {
+        match result {
+            Err(err) => match err.downcast_ref::<InstallError>() {
+                Some(&InstallError::Archive { .. }) => {}
+                _ => panic!("expected an archive error, got {}", err),
+            },
+            Ok(count) => panic!("extracted {} entries from an unsafe archive", count),
+        }
+    }
+
+    #[test]
+    fn entry_path_strips_components_and_selects_archive_dir() {
+        let archive = PathBuf::from("test.zip");
+        let path = |name, strip, dir| entry_path(&archive, name, strip, dir).unwrap();
+        assert_eq!(path("top/a/b.js", 1, ""), Some(PathBuf::from("a/b.js")));
+        assert_eq!(path("top/js/b.js", 1, "js/"), Some(PathBuf::from("b.js")));
+        assert_eq!(path("top/src/b.c", 1, "js/"), None);
+        assert_eq!(path("top/js/", 1, "js/"), None);
+        assert_eq!(path("top/", 1, ""), None);
+        assert_eq!(path("./top//a\\b.js", 1, ""), Some(PathBuf::from("a/b.js")));
+    }
+
+    #[test]
+    fn entry_path_rejects_names_leaving_the_dir() {
+        let archive = PathBuf::from("test.zip");
+        for name in ["../a", "top/../../a", "top/a/../b", "/etc/passwd", "\\a\\b"].iter() {
+            assert!(
+                entry_path(&archive, name, 0, "").is_err(),
+                "accepted {}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn link_targets_must_stay_inside_the_dir() {
+        let archive = PathBuf::from("test.zip");
+        let check = |name: &str, target: &str| {
+            check_link_target(
+                &archive,
+                &Link {
+                    name: name.to_string(),
+                    relative: PathBuf::from(name),
+                    target: target.to_string(),
+                },
+            )
+        };
+        assert!(check("a/link", "file").is_ok());
+        assert!(check("a/link", "../file").is_ok());
+        assert!(check("a/b/link", "../../c/file").is_ok());
+        assert!(check("link", "../file").is_err());
+        assert!(check("a/link", "../../file").is_err());
+        assert!(check("a/link", "c/../../file").is_err());
+        assert!(check("a/link", "/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn extracts_zip_files_dirs_modes_and_links() {
+        let dir = scratch_dir("zip-good");
+        let archive = write_archive(
+            &dir,
+            "download",
+            &zip_bytes(&[
+                ("top/", DIR | 0o755, b""),
+                ("top/bin/", DIR | 0o755, b""),
+                ("top/bin/run", FILE | 0o755, b"#!/bin/sh\n"),
+                ("top/lib/data.txt", FILE | 0o644, b"data"),
+                ("top/bin/data", SYMLINK | 0o777, b"../lib/data.txt"),
+            ]),
+        );
+        let dest = dir.join("out");
+        assert_eq!(extract_archive(&archive, &dest, 1, "").unwrap(), 4);
+        assert_eq!(read(&dest.join("lib").join("data.txt")), "data");
+        assert_eq!(read(&dest.join("bin").join("data")), "data");
+        assert!(is_symlink(&dest.join("bin").join("data")));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = fs::metadata(dest.join("bin").join("run"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o755);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_zip_entries_leaving_the_dir() {
+        let dir = scratch_dir("zip-slip");
+        let dest = dir.join("out");
+        for name in ["../evil", "/tmp/sky-install-evil", "a/../../evil"].iter() {
+            let archive =
+                write_archive(&dir, "slip.zip", &zip_bytes(&[(name, FILE | 0o644, b"x")]));
+            assert_refused(extract_archive(&archive, &dest, 0, ""));
+        }
+        assert!(!dir.join("evil").exists());
+        assert!(!PathBuf::from("/tmp/sky-install-evil").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_zip_links_leaving_the_dir() {
+        let dir = scratch_dir("zip-links");
+        let dest = dir.join("out");
+        for target in ["../outside", "/etc/passwd"].iter() {
+            let archive = write_archive(
+                &dir,
+                "links.zip",
+                &zip_bytes(&[("link", SYMLINK | 0o777, target.as_bytes())]),
+            );
+            assert_refused(extract_archive(&archive, &dest, 0, ""));
+            assert!(!is_symlink(&dest.join("link")));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn never_writes_through_a_symlink() {
+        use std::os::unix::fs::symlink;
+
+        let dir = scratch_dir("zip-through-link");
+        let outside = dir.join("outside");
+        fs::create_dir_all(&outside).unwrap();
+        let dest = dir.join("out");
+        fs::create_dir_all(&dest).unwrap();
+
+        // a directory that is a symlink to elsewhere is not extracted into
+        symlink(&outside, dest.join("linked")).unwrap();
+        let archive = write_archive(
+            &dir,
+            "through.zip",
+            &zip_bytes(&[("linked/file", FILE | 0o644, b"x")]),
+        );
+        assert_refused(extract_archive(&archive, &dest, 0, ""));
+        assert!(!outside.join("file").exists());
+
+        // a file that is a symlink is replaced rather than written through
+        write_archive(&outside, "target", b"untouched");
+        symlink(outside.join("target"), dest.join("file")).unwrap();
+        let archive = write_archive(
+            &dir,
+            "replace.zip",
+            &zip_bytes(&[("file", FILE | 0o644, b"new")]),
+        );
+        extract_archive(&archive, &dest, 0, "").unwrap();
+        assert_eq!(read(&outside.join("target")), "untouched");
+        assert!(!is_symlink(&dest.join("file")));
+        assert_eq!(read(&dest.join("file")), "new");
+
+        // nor does a link in the archive let a later entry escape
+        let archive = write_archive(
+            &dir,
+            "link-then-file.zip",
+            &zip_bytes(&[
+                ("escape", SYMLINK | 0o777, b"../outside"),
+                ("escape/file", FILE | 0o644, b"x"),
+            ]),
+        );
+        assert!(extract_archive(&archive, &dir.join("fresh"), 0, "").is_err());
+        assert!(!outside.join("file").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tells_the_format_from_the_first_bytes_not_the_name() {
+        let dir = scratch_dir("formats");
+        let tar = tar_bytes(&[("a", EntryType::Regular, 0o644, b"a")]);
+        let zip = zip_bytes(&[("a", FILE | 0o644, b"a")]);
+        let cases = vec![
+            ("zip.tar.gz", zip.clone(), ArchiveFormat::Zip),
+            ("tar.zip", tar.clone(), ArchiveFormat::Tar),
+            ("tgz", gzip(&tar), ArchiveFormat::TarGz),
+            ("txz.zip", xz(&tar), ArchiveFormat::TarXz),
+        ];
+        for (name, bytes, format) in cases {
+            let archive = write_archive(&dir, name, &bytes);
+            assert_eq!(archive_format(&archive).unwrap(), format, "{}", name);
+        }
+        for &(name, bytes) in [("empty.zip", &b""[..]), ("page.tar.gz", &b"<html>"[..])].iter() {
+            let archive = write_archive(&dir, name, bytes);
+            assert!(archive_format(&archive).is_err(), "{}", name);
+            assert!(extract_archive(&archive, &dir.join("out"), 0, "").is_err());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn extracts_compressed_tars_with_modes_and_links() {
+        let tar = tar_bytes(&[
+            ("pax_global_header", EntryType::XGlobalHeader, 0o644, b""),
+            ("top/js/", EntryType::Directory, 0o755, b""),
+            ("top/js/message.js", EntryType::Regular, 0o640, b"message"),
+            ("top/js/bin/run", EntryType::Regular, 0o755, b"run"),
+            ("top/js/alias.js", EntryType::Symlink, 0o777, b"message.js"),
+            (
+                "top/js/hard.js",
+                EntryType::Link,
+                0o644,
+                b"top/js/message.js",
+            ),
+            ("top/src/lib.c", EntryType::Regular, 0o644, b"c"),
+        ]);
+        for &(name, ref bytes) in [("tar.gz", gzip(&tar)), ("tar.xz", xz(&tar))].iter() {
+            let dir = scratch_dir(name);
+            let archive = write_archive(&dir, "download", bytes);
+            let dest = dir.join("out");
+            assert_eq!(extract_archive(&archive, &dest, 1, "js/").unwrap(), 3);
+            assert_eq!(read(&dest.join("message.js")), "message");
+            assert_eq!(read(&dest.join("alias.js")), "message");
+            assert!(!dest.join("hard.js").exists());
+            assert!(!dest.join("lib.c").exists());
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+
+                let mode = |path: PathBuf| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+                assert_eq!(mode(dest.join("message.js")), 0o640);
+                assert_eq!(mode(dest.join("bin").join("run")), 0o755);
+            }
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn refuses_tar_entries_and_links_leaving_the_dir() {
+        let dir = scratch_dir("tar-slip");
+        let dest = dir.join("out");
+        let unsafe_entries: Vec<(&str, EntryType, &[u8])> = vec![
+            ("../evil", EntryType::Regular, b"x"),
+            ("/tmp/sky-install-evil", EntryType::Regular, b"x"),
+            ("a/../../evil", EntryType::Directory, b""),
+            ("a/link", EntryType::Symlink, b"../../evil"),
+            ("link", EntryType::Symlink, b"/etc/passwd"),
+        ];
+        for (name, entry_type, contents) in unsafe_entries {
+            let tar = tar_bytes(&[(name, entry_type, 0o644, contents)]);
+            let archive = write_archive(&dir, "slip.tar", &tar);
+            assert_refused(extract_archive(&archive, &dest, 0, ""));
+        }
+        assert!(!dir.join("evil").exists());
+        assert!(!PathBuf::from("/tmp/sky-install-evil").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
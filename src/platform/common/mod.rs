@@ -1,155 +1,286 @@
+use config::Config;
+use lockfile::Artifact;
+
+mod download;
+
+mod extract;
+
+use self::download::{discard_partial, download_using_curl};
+pub use self::extract::extract_archive;
 use std::error::Error;
 use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::process::Output;
+use Args;
 
-pub fn verify_git_clone_success(result_string: &str) -> Result<(), Box<Error>> {
-    use error::InstallError;
+// the versions of the viz javascript dependencies installed into viz/js.  Each install
+// leaves a marker file recording what was fetched, so updates can keep a matching one
+// and 'lock' can pin it.
+pub const CLOSURE_LIBRARY_VERSION: &'static str = "20171112";
+const VERSION_MARKER_FILENAME: &'static str = ".sky-install-version";
 
-    if result_string.starts_with("error") || result_string.starts_with("fatal") {
-        return Err(Box::new(InstallError::new(format!(
-            "ERROR - git pull failed : {}",
-            result_string
-        ))));
-    }
-    Ok(())
-}
+// the protobuf release whose javascript is installed, unless config.toml names another
+pub const DEFAULT_PROTOBUF_JS_VERSION: &'static str = "3.5.1";
+
+// where everything is fetched from unless config.toml's [repos] says otherwise.  {version}
+// is replaced with the version being downloaded.
+pub const SCAII_URL: &'static str = "https://github.com/SCAII/SCAII.git";
+pub const SKY_RTS_URL: &'static str = "https://github.com/SCAII/Sky-RTS.git";
+const CLOSURE_LIBRARY_URL: &'static str =
+    "https://github.com/google/closure-library/archive/v{version}.zip";
+const PROTOBUF_JS_URL: &'static str = "https://github.com/google/protobuf/archive/v{version}.zip";
+
+// where Sky-RTS sits in the SCAII checkout, as a submodule
+pub const SKY_RTS_SUBMODULE_PATH: &'static str = "backends/sky-rts";
 
-#[inline]
-pub fn protoc_hack(command: String) -> String {
-    if command == "\"protoc\"" {
-        String::from("protoc")
+// what get_core checks out after cloning: the --rev given, else the requested branch,
+// else the remote's default branch
+pub fn clone_target(command_args: &Args) -> Option<&str> {
+    if command_args.arg_rev != "" {
+        Some(&command_args.arg_rev)
+    } else if command_args.flag_branch {
+        Some(&command_args.arg_branch_name)
     } else {
-        command
+        None
     }
 }
 
-pub fn checkout(branch: String) -> Result<(), Box<Error>> {
-    use error::InstallError;
-    use platform;
+// the urls to download the given version of the named archive from, in the order to try
+// them
+pub fn archive_urls(config: &Config, name: &str, default_url: &str, version: &str) -> Vec<String> {
+    config
+        .repo_urls(name, default_url)
+        .iter()
+        .map(|url| url.replace("{version}", version))
+        .collect()
+}
 
-    let command: String = "git".to_string();
-    let mut args: Vec<String> = Vec::new();
-    args.push("checkout".to_string());
-    args.push(branch.clone());
-    let result_string = platform::run_command(&command, args)?;
-    if !result_string.starts_with("error") {
-        return Ok(());
-    } else {
-        return Err(Box::new(InstallError::new(format!(
-            "ERROR - problem checking out branch {} : {}",
-            branch, result_string
-        ))));
+// the urls to clone submodules from, by path, for those config.toml overrides.  Others
+// are cloned from the url in .gitmodules.
+pub fn submodule_urls(config: &Config) -> Vec<(String, Vec<String>)> {
+    use lockfile::ARTIFACT_SKY_RTS;
+
+    let mut submodules = Vec::new();
+    if config.repos.contains_key(ARTIFACT_SKY_RTS) {
+        submodules.push((
+            SKY_RTS_SUBMODULE_PATH.to_string(),
+            config.repo_urls(ARTIFACT_SKY_RTS, SKY_RTS_URL),
+        ));
     }
+    submodules
 }
 
-pub fn ensure_google_closure_lib_installed(scaii_root: PathBuf) -> Result<(), Box<Error>> {
+// locked is the lockfile's entry for the closure library when installing with --locked
+pub fn ensure_google_closure_lib_installed(
+    scaii_root: PathBuf,
+    config: &Config,
+    locked: Option<&Artifact>,
+) -> Result<(), Box<Error>> {
     use error::InstallError;
+    use lockfile::ARTIFACT_CLOSURE_LIBRARY;
+    use platform;
 
     //\SCAII\viz\js\closure-library\closure\bin
     let mut closure_dir = scaii_root.clone();
     closure_dir.push("viz");
     closure_dir.push("js");
     closure_dir.push("closure-library");
-    if closure_dir.as_path().exists() {
+    if installed_artifact_matches(&closure_dir, CLOSURE_LIBRARY_VERSION, locked)? {
         println!("closure library already installed at {:?}.", closure_dir);
-        Ok(())
-    } else {
-        println!("...installing google closure library");
-        let mut closure_install_dir = scaii_root;
-        closure_install_dir.push("viz");
-        closure_install_dir.push("js");
-
-        //
-        //  WEIRD - I was trying to download version v20170910 that I have been using all along
-        // (and thus testing against).  It turns out the version number in the package.json file are
-        // a release behind, so I had to download v20171112.zip to get this desired version:
-        // "version": "20170910.0.0",
-        //
-        //let filename = String::from("v20170910.zip");
-        //let url = String::from("https://github.com/google/closure-library/archive/v20170910.zip");
-        let filename = String::from("v20171112.zip");
-        let url = String::from("https://github.com/google/closure-library/archive/v20171112.zip");
-        let install_result = install_google_closure_library(
-            closure_install_dir,
-            url,
-            filename,
-            String::from("closure-library-20171112"),
+        return Ok(());
+    }
+    if closure_dir.as_path().exists() {
+        println!(
+            "...replacing closure library with v{}",
+            CLOSURE_LIBRARY_VERSION
         );
-        match install_result {
-            Ok(_) => Ok(()),
-            Err(error) => Err(Box::new(InstallError::new(format!(
-                "google closure library download appears to have failed: {:?}",
-                error.description()
-            )))),
-        }
+        platform::remove_tree(&closure_dir)?;
     }
+    println!("...installing google closure library");
+    let mut closure_install_dir = scaii_root;
+    closure_install_dir.push("viz");
+    closure_install_dir.push("js");
+
+    //
+    //  WEIRD - I was trying to download version v20170910 that I have been using all along
+    // (and thus testing against).  It turns out the version number in the package.json file are
+    // a release behind, so I had to download v20171112.zip to get this desired version:
+    // "version": "20170910.0.0",
+    //
+    //let url = String::from("https://github.com/google/closure-library/archive/v20170910.zip");
+    let pinned_sha256 = match locked {
+        Some(locked) => locked.sha256.clone(),
+        None => None,
+    };
+    let urls = archive_urls(
+        config,
+        ARTIFACT_CLOSURE_LIBRARY,
+        CLOSURE_LIBRARY_URL,
+        CLOSURE_LIBRARY_VERSION,
+    );
+    let (closure_dir, sha256) = install_archive(
+        &closure_install_dir,
+        &urls,
+        1,
+        "",
+        "closure-library",
+        config,
+        pinned_sha256,
+    )?;
+    if !closure_dir
+        .join("closure")
+        .join("goog")
+        .join("base.js")
+        .exists()
+    {
+        return Err(Box::new(InstallError::network(
+            &urls[0],
+            format!(
+                "google closure library download appears to have failed - {:?} is incomplete",
+                closure_dir
+            ),
+        )));
+    }
+    let mut artifact = Artifact::new(ARTIFACT_CLOSURE_LIBRARY, &urls[0]);
+    artifact.version = Some(CLOSURE_LIBRARY_VERSION.to_string());
+    artifact.sha256 = Some(sha256);
+    if let Some(locked) = locked {
+        locked.verify(&artifact)?;
+    }
+    write_artifact_marker(&closure_dir, &artifact)
 }
 
-pub fn install_google_closure_library(
-    mut closure_install_dir: PathBuf,
-    url: String,
-    filename: String,
-    orig_unzipped_dir_name: String,
-) -> Result<PathBuf, Box<Error>> {
+// Takes the archive at urls[0] - a zip, tar, tar.gz or tar.xz - from the download cache,
+// or downloads it into install_dir, trying the other urls, its mirrors, in turn if it
+// can't be, and checks its SHA-256 (pinned_sha256 if given, else the one checksums knows
+// for urls[0]) before moving it into the cache.  The first strip_components directories
+// of each entry are dropped and only the entries under archive_dir (a dir inside what
+// remains, ending in '/', or "" for everything) are extracted into install_dir/dest_name.
+// Returns the installed dir and the SHA-256 of the download.
+pub fn install_archive(
+    install_dir: &PathBuf,
+    urls: &Vec<String>,
+    strip_components: usize,
+    archive_dir: &str,
+    dest_name: &str,
+    config: &Config,
+    pinned_sha256: Option<String>,
+) -> Result<(PathBuf, String), Box<Error>> {
+    use cache;
+    use checksums;
     use error::InstallError;
-    use std::env;
     use std::fs;
 
-    println!("...cd {:?}", closure_install_dir);
-    env::set_current_dir(&closure_install_dir)?;
-    let mut closure_zip_path: PathBuf = closure_install_dir.clone();
-    closure_zip_path.push(filename);
-    println!("...downloading closure zip");
-    let curl_result = download_using_curl(&url, &closure_zip_path);
-    match curl_result {
-        Ok(_) => {
+    // mirrors serve the same file, so it is checked and cached under the first url
+    let url: &str = &urls[0];
+    let filename = match url.rsplit('/').next() {
+        Some(filename) if filename != "" => filename.to_string(),
+        _ => format!("{}.zip", dest_name),
+    };
+    let archive_path = install_dir.join(format!("{}-{}", dest_name, filename));
+    let expected_sha256 = match pinned_sha256 {
+        Some(pinned) => Some(pinned.to_lowercase()),
+        None => checksums::expected_sha256(config, url)?,
+    };
+    let use_cache = cache::enabled(config);
+    let cached = match expected_sha256 {
+        _ if !use_cache => None,
+        Some(ref expected) => cache::lookup(config, url, Some(expected))?,
+        None => cache::lookup(config, url, None)?,
+    };
+    let (cached_path, sha256) = match cached {
+        Some((cached_path, sha256)) => {
+            println!("...using cached download of {}", url);
+            if expected_sha256.is_none() {
+                checksums::record_sha256(config, url, &sha256)?;
+            }
+            (cached_path, sha256)
+        }
+        None => {
+            download_from_mirrors(urls, &archive_path, config)?;
             // verify expected file exists
-            if !closure_zip_path.as_path().exists() {
-                println!("ERROR - google closure library install failed.");
-                Err(Box::new(InstallError::new(format!(
-                    "google closure library download appears to have failed \
-                     - file not present {:?}",
-                    closure_zip_path
-                ))))
-            } else {
-                println!("...unzipping");
-                let f = fs::File::open(&closure_zip_path)?;
-                unzip_file(&closure_install_dir, f)?;
-                let mut closure_temp_dir_name = closure_install_dir.clone();
-                closure_temp_dir_name.push(&orig_unzipped_dir_name);
-
-                closure_install_dir.push("closure-library");
-                let rename_result = fs::rename(&orig_unzipped_dir_name, &closure_install_dir);
-                let result = match rename_result {
-                    Ok(_) => {
-                        if closure_install_dir.exists() {
-                            Ok(closure_install_dir)
-                        } else {
-                            Err(Box::new(InstallError::new(format!(
-                                "{:?} does not exist after unzipping closure bundle.",
-                                closure_install_dir
-                            ))))
-                        }
-                    }
-                    Err(error) => {
-                        println!("{}", error.description());
-                        Err(Box::new(InstallError::new(format!(
-                            "could not rename {:?} to {:?}.",
-                            closure_temp_dir_name, closure_install_dir
-                        ))))
+            if !archive_path.as_path().exists() {
+                return Err(Box::new(InstallError::network(
+                    url,
+                    format!(
+                        "download appears to have failed - file not present {:?}",
+                        archive_path
+                    ),
+                )));
+            }
+            let sha256 = sha256_file(&archive_path)?;
+            match expected_sha256 {
+                Some(expected) => {
+                    if expected != sha256 {
+                        fs::remove_file(&archive_path)?;
+                        return Err(Box::new(InstallError::Checksum {
+                            url: url.to_string(),
+                            expected: expected,
+                            actual: sha256,
+                        }));
                     }
-                }?;
-                fs::remove_file(closure_zip_path)?;
-                Ok(result)
+                    println!("...verified SHA-256 {}", sha256);
+                }
+                None => checksums::record_sha256(config, url, &sha256)?,
+            }
+            if use_cache {
+                (cache::store(config, url, &archive_path, &sha256)?, sha256)
+            } else {
+                (archive_path.clone(), sha256)
             }
         }
-        Err(error) => Err(Box::new(InstallError::new(format!(
-            "tried using curl library to download protoc from {} , but hit error: {}",
+    };
+
+    println!("...extracting");
+    let dest_dir = install_dir.join(dest_name);
+    let extract_result = extract_archive(&cached_path, &dest_dir, strip_components, archive_dir);
+    if !use_cache {
+        fs::remove_file(&archive_path)?;
+    }
+    if extract_result? == 0 {
+        return Err(Box::new(InstallError::network(
             url,
-            error.description()
-        )))),
+            format!("the download has nothing to install into {:?}", dest_dir),
+        )));
     }
+    Ok((dest_dir, sha256))
+}
+
+// downloads the first of urls that can be reached into archive_path
+pub fn download_from_mirrors(
+    urls: &Vec<String>,
+    archive_path: &PathBuf,
+    config: &Config,
+) -> Result<(), Box<Error>> {
+    use error::InstallError;
+
+    let mut failures = Vec::new();
+    let mut last_error = None;
+    for (index, url) in urls.iter().enumerate() {
+        if index > 0 {
+            discard_partial(archive_path);
+        }
+        println!("...downloading {}", url);
+        match download_using_curl(url, archive_path, config) {
+            Ok(()) => return Ok(()),
+            Err(error) => {
+                println!("could not download {}: {}", url, error);
+                failures.push(format!("{} ({})", url, error));
+                last_error = Some(error);
+            }
+        }
+    }
+    let err = InstallError::network(
+        &urls.join(", "),
+        format!(
+            "tried using curl library to download, but every attempt failed: {}",
+            failures.join("; ")
+        ),
+    );
+    Err(Box::new(match last_error {
+        Some(last_error) => err.caused_by(last_error),
+        None => err,
+    }))
 }
 
 pub fn copy_source_named(
@@ -158,8 +289,19 @@ pub fn copy_source_named(
     source_filename: String,
     dest_filename: String,
 ) -> Result<(), Box<Error>> {
-    let mut source: PathBuf = source_dir;
+    use error::InstallError;
+
+    let mut source: PathBuf = source_dir.clone();
     source.push(source_filename);
+    if !source.as_path().exists() {
+        return Err(Box::new(InstallError::build(
+            &source_dir,
+            format!(
+                "ERROR - expected build output {:?} was not found - did the cargo build succeed?",
+                source
+            ),
+        )));
+    }
     let mut target: PathBuf = target_dir;
     target.push(dest_filename);
     copy_file(&source, &target)?;
@@ -176,83 +318,59 @@ pub fn copy_file<P1: AsRef<Path>, P2: AsRef<Path>>(source: P1, dest: P2) -> Resu
     let copy_result = fs::copy(src, dst);
     match copy_result {
         Ok(_) => Ok(()),
-        Err(err) => Err(Box::new(InstallError::new(format!(
-            "ERROR - could not copy core binary: {:?}",
-            err.description()
-        )))),
+        Err(err) => Err(Box::new(
+            InstallError::filesystem(
+                &source.as_ref().to_path_buf(),
+                format!("ERROR - could not copy to {}", dst),
+            )
+            .caused_by(Box::new(err)),
+        )),
     }
 }
 
-fn download_using_curl(url: &String, target_path: &PathBuf) -> Result<(), Box<Error>> {
-    use curl::easy::{Easy2, Handler, WriteError};
-    use std::fs;
-    use std::io::Write;
-
-    struct Collector(Vec<u8>);
+pub fn sha256_file(path: &Path) -> Result<String, Box<Error>> {
+    use sha2::{Digest, Sha256};
+    use std::io::Read;
 
-    impl Handler for Collector {
-        fn write(&mut self, data: &[u8]) -> Result<usize, WriteError> {
-            self.0.extend_from_slice(data);
-            Ok(data.len())
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::default();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let count = file.read(&mut buf)?;
+        if count == 0 {
+            break;
         }
+        hasher.input(&buf[..count]);
     }
-
-    let mut easy = Easy2::new(Collector(Vec::new()));
-    easy.get(true).unwrap();
-    easy.follow_location(true).unwrap();
-    easy.url(url).unwrap();
-    easy.perform().unwrap();
-
-    assert_eq!(easy.response_code().unwrap(), 200);
-    let contents = easy.get_ref();
-    let mut output_file = fs::File::create(target_path)?;
-    output_file.write_all(&contents.0)?;
-    Ok(())
-}
-
-fn append_relative_path(mut path_buf: PathBuf, subdir: &str) -> PathBuf {
-    let parts_iter = subdir.split("/");
-    for part in parts_iter {
-        match part {
-            "" => {
-                // do nothing
-            }
-            ref x => {
-                path_buf.push(x);
-            }
-        }
+    let digest = hasher.result();
+    let mut hex = String::with_capacity(digest.len() * 2);
+    for byte in digest.iter() {
+        hex.push_str(&format!("{:02x}", byte));
     }
-    path_buf
+    Ok(hex)
 }
 
-fn unzip_file(parent: &PathBuf, zip_file: File) -> Result<(), Box<Error>> {
-    use std::fs;
-    use std::io::{Read, Write};
-    use zip;
+// platform::run_command passes stdout through to the console, so commands whose output
+// is needed run through here instead
+pub fn capture_command(command: &str, args: Vec<String>) -> Result<String, Box<Error>> {
+    use error::InstallError;
+    use std::process::Command;
 
-    let mut zip = try!(zip::ZipArchive::new(&zip_file));
-    println!(
-        "unzipping {:?}... zip file count is {}",
-        zip_file,
-        zip.len()
-    );
-    for i in 0..zip.len() {
-        let mut zip_file = zip.by_index(i).unwrap();
-        let file_size = zip_file.size();
-        match file_size {
-            0 => {
-                ensure_subdir_exists(parent.clone(), zip_file.name())?;
-            }
-            _ => {
-                let path = append_relative_path(parent.clone(), zip_file.name());
-                let mut buf: Vec<u8> = Vec::new();
-                let _read_result_usize = zip_file.read_to_end(&mut buf)?;
-                let mut output_file = fs::File::create(path)?;
-                output_file.write_all(buf.as_slice())?;
-            }
-        }
+    let output = Command::new(command).args(&args).output().map_err(|err| {
+        InstallError::Prerequisite(format!(
+            "could not run {} - is it installed? {}",
+            command, err
+        ))
+    })?;
+    emit_error_output(&output);
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(Box::new(InstallError::command(
+            &format!("{} {}", command, args.join(" ")),
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        )))
     }
-    Ok(())
 }
 
 pub fn emit_error_output(output: &Output) {
@@ -270,53 +388,134 @@ pub fn ensure_dir_exists(path_buf: &PathBuf) -> Result<(), Box<Error>> {
     Ok(())
 }
 
-fn ensure_subdir_exists(mut path_buf: PathBuf, subdir: &str) -> Result<(), Box<Error>> {
-    let parts_iter = subdir.split("/");
-    for part in parts_iter {
-        match part {
-            "" => {
-                // do nothing
-            }
-            ref x => {
-                path_buf.push(x);
-                ensure_dir_exists(&path_buf)?;
-            }
+// Installs the javascript from a protobuf release archive, the one named in config.toml
+// or the default.  locked is the lockfile's entry for it when installing with --locked.
+pub fn ensure_protobuf_javascript_lib_installed(
+    scaii_root: PathBuf,
+    config: &Config,
+    locked: Option<&Artifact>,
+) -> Result<(), Box<Error>> {
+    use error::InstallError;
+    use lockfile::ARTIFACT_PROTOBUF_JS;
+    use platform;
+
+    let (version, pinned_sha256) = match locked {
+        Some(locked) => (locked.version.clone(), locked.sha256.clone()),
+        None => (
+            config.protobuf_js_version.clone(),
+            config.protobuf_js_sha256.clone(),
+        ),
+    };
+    let version = version.unwrap_or(DEFAULT_PROTOBUF_JS_VERSION.to_string());
+    let urls = archive_urls(config, ARTIFACT_PROTOBUF_JS, PROTOBUF_JS_URL, &version);
+    // another release is only unpacked once its digest is known, from protobuf_js_sha256
+    // or from [checksums]
+    if version != DEFAULT_PROTOBUF_JS_VERSION
+        && pinned_sha256.is_none()
+        && !config.checksums.contains_key(&urls[0])
+    {
+        return Err(Box::new(InstallError::Usage(format!(
+            "protobuf_js_version is set to {} but protobuf_js_sha256 is not - set it to the \
+             SHA-256 of {}",
+            version, urls[0]
+        ))));
+    }
+    let mut protobuf_js_dir = scaii_root.clone();
+    protobuf_js_dir.push("viz");
+    protobuf_js_dir.push("js");
+    protobuf_js_dir.push("protobuf_js");
+    if installed_artifact_matches(&protobuf_js_dir, &version, locked)? {
+        println!(
+            "protobuf javascript library already installed at {:?}.",
+            protobuf_js_dir
+        );
+        return Ok(());
+    }
+    if protobuf_js_dir.as_path().exists() {
+        platform::remove_tree(&protobuf_js_dir)?;
+    }
+
+    println!(
+        "...installing google protobuf javascript library v{}",
+        version
+    );
+    let (protobuf_js_dir, sha256) = install_archive(
+        &scaii_root.join("viz").join("js"),
+        &urls,
+        1,
+        "js/",
+        "protobuf_js",
+        config,
+        pinned_sha256,
+    )?;
+    if !protobuf_js_dir.join("message.js").exists() {
+        return Err(Box::new(InstallError::network(
+            &urls[0],
+            format!(
+                "protobuf v{} javascript appears incomplete - {:?} has no message.js",
+                version, protobuf_js_dir
+            ),
+        )));
+    }
+    let mut artifact = Artifact::new(ARTIFACT_PROTOBUF_JS, &urls[0]);
+    artifact.version = Some(version);
+    artifact.sha256 = Some(sha256);
+    if let Some(locked) = locked {
+        locked.verify(&artifact)?;
+    }
+    write_artifact_marker(&protobuf_js_dir, &artifact)
+}
+
+// true if dir holds the given version of a dependency (and the locked artifact, if
+// given).  Dependencies installed before markers were written have none, so they are
+// always replaced.
+fn installed_artifact_matches(
+    dir: &PathBuf,
+    version: &str,
+    locked: Option<&Artifact>,
+) -> Result<bool, Box<Error>> {
+    if !dir.as_path().exists() {
+        return Ok(false);
+    }
+    match read_artifact_marker(dir)? {
+        Some(installed) => {
+            let locked_matches = match locked {
+                Some(locked) => locked.verify(&installed).is_ok(),
+                None => true,
+            };
+            Ok(installed.version == Some(version.to_string()) && locked_matches)
         }
+        None => Ok(false),
     }
-    Ok(())
 }
 
-pub fn install_protobuf_javascript_lib(install_dir: PathBuf) -> Result<(), Box<Error>> {
-    use platform;
-    use std::env;
-
-    println!("...installing google protobuf javascript library...");
-    let orig_dir_pathbuf = env::current_dir()?;
-    let mut js_dir = install_dir.clone();
-    js_dir.push("viz".to_string());
-    js_dir.push("js".to_string());
-    println!("...cd {:?}", js_dir);
-    env::set_current_dir(js_dir.as_path())?;
-    let command: String = "git".to_string();
-    let mut args: Vec<String> = Vec::new();
-    args.push("clone".to_string());
-    args.push("https://github.com/google/protobuf".to_string());
-    println!("...cloning repo");
-    let result_string = platform::run_command(&protoc_hack(command.clone()), args)?;
-    verify_git_clone_success(&result_string)?;
-
-    let mut protobuf_slash_js_dir = js_dir.clone();
-    protobuf_slash_js_dir.push("protobuf".to_string());
-    protobuf_slash_js_dir.push("js".to_string());
-
-    let mut protobuf_js_dir = js_dir.clone();
-    protobuf_js_dir.push("protobuf_js".to_string());
-    println!("...copying javascript portion");
-    platform::copy_recursive(protobuf_slash_js_dir, &protobuf_js_dir)?;
-
-    let mut protobuf_dir = js_dir.clone();
-    protobuf_dir.push("protobuf".to_string());
-    platform::remove_tree(&protobuf_dir)?;
-    env::set_current_dir(orig_dir_pathbuf.as_path())?;
+// what was fetched into dir, if it was installed by a version of sky-install that
+// records it.  Early markers held only the version.
+pub fn read_artifact_marker(dir: &PathBuf) -> Result<Option<Artifact>, Box<Error>> {
+    use std::io::Read;
+    use toml;
+
+    let marker = dir.join(VERSION_MARKER_FILENAME);
+    if !marker.as_path().exists() {
+        return Ok(None);
+    }
+    let mut contents = String::new();
+    File::open(&marker)?.read_to_string(&mut contents)?;
+    match toml::from_str(&contents) {
+        Ok(artifact) => Ok(Some(artifact)),
+        Err(_) => {
+            let mut artifact = Artifact::default();
+            artifact.version = Some(contents.trim().to_string());
+            Ok(Some(artifact))
+        }
+    }
+}
+
+fn write_artifact_marker(dir: &PathBuf, artifact: &Artifact) -> Result<(), Box<Error>> {
+    use std::io::Write;
+    use toml;
+
+    let mut marker = File::create(dir.join(VERSION_MARKER_FILENAME))?;
+    marker.write_all(toml::to_string(artifact)?.as_bytes())?;
     Ok(())
 }
@@ -0,0 +1,544 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use super::git2;
+use super::git2::build::{CheckoutBuilder, RepoBuilder};
+use super::git2::{
+    AutotagOption, BranchType, ErrorClass, FetchOptions, Oid, ProxyOptions, RemoteCallbacks,
+    Repository, Status, StatusOptions, SubmoduleUpdateOptions,
+};
+use error::InstallError;
+
+// Clones, fetches and checkouts go through libgit2 rather than the git command line, so a
+// failure comes back as a git2::Error, which is kept as the cause of the InstallError
+// saying what was being done and to which repository.
+fn context<T>(
+    result: Result<T, git2::Error>,
+    repo: &str,
+    action: String,
+) -> Result<T, InstallError> {
+    result.map_err(|cause| {
+        // failing to reach a remote is a network problem rather than a problem with the
+        // checkout
+        let err = match cause.class() {
+            ErrorClass::Net | ErrorClass::Ssh | ErrorClass::Ssl => {
+                InstallError::network(repo, action)
+            }
+            _ => InstallError::git(repo, action),
+        };
+        err.caused_by(Box::new(cause))
+    })
+}
+
+// where a repository is, for error messages
+fn repo_name(repo: &Repository) -> String {
+    repo.workdir()
+        .unwrap_or(repo.path())
+        .to_string_lossy()
+        .to_string()
+}
+
+// clones the first of urls that can be reached into dest, then checks out target (a
+// branch, tag or commit SHA) if given, otherwise the remote's default branch.  Submodules
+// listed in submodule_urls are cloned from those urls instead of .gitmodules'.
+pub fn clone(
+    urls: &Vec<String>,
+    dest: &PathBuf,
+    target: Option<&str>,
+    submodule_urls: &Vec<(String, Vec<String>)>,
+) -> Result<(), Box<Error>> {
+    use platform;
+
+    let mut last_error = None;
+    for url in urls.iter() {
+        println!("cloning {} into {:?}...", url, dest);
+        let mut builder = RepoBuilder::new();
+        builder.fetch_options(fetch_options());
+        builder.with_checkout(checkout_options());
+        match context(
+            builder.clone(url, dest),
+            url,
+            format!("could not clone into {:?}", dest),
+        ) {
+            Ok(repo) => {
+                println!("");
+                if let Some(target) = target {
+                    checkout(&repo, target)?;
+                }
+                return update_submodules(&repo, submodule_urls);
+            }
+            Err(err) => {
+                // clear out what was fetched so the next mirror starts afresh
+                println!("\n{}", err);
+                if dest.as_path().exists() {
+                    platform::remove_tree(dest)?;
+                }
+                last_error = Some(err);
+            }
+        }
+    }
+    match last_error {
+        Some(err) => Err(Box::new(err)),
+        None => Err(Box::new(InstallError::Usage(format!(
+            "no url configured to clone {:?} from",
+            dest
+        )))),
+    }
+}
+
+// fetches origin into an existing checkout and moves it to target
+pub fn update(
+    repo_dir: &PathBuf,
+    target: &str,
+    submodule_urls: &Vec<(String, Vec<String>)>,
+) -> Result<(), Box<Error>> {
+    println!("fetching {:?}...", repo_dir);
+    let repo_dir_name = repo_dir.to_string_lossy().to_string();
+    let repo = context(
+        Repository::open(repo_dir),
+        &repo_dir_name,
+        "could not open the checkout".to_string(),
+    )?;
+    {
+        let mut remote = context(
+            repo.find_remote("origin"),
+            &repo_dir_name,
+            "the checkout has no origin remote".to_string(),
+        )?;
+        let remote_url = remote.url().unwrap_or(&repo_dir_name).to_string();
+        context(
+            remote.fetch(&[], Some(&mut fetch_options()), None),
+            &remote_url,
+            format!("could not fetch into {:?}", repo_dir),
+        )?;
+    }
+    println!("");
+    checkout(&repo, target)?;
+    update_submodules(&repo, submodule_urls)
+}
+
+// the commit HEAD points at in repo_dir
+pub fn head_commit(repo_dir: &PathBuf) -> Result<String, Box<Error>> {
+    let repo_dir_name = repo_dir.to_string_lossy().to_string();
+    let repo = context(
+        Repository::open(repo_dir),
+        &repo_dir_name,
+        "could not open the checkout".to_string(),
+    )?;
+    let head = context(
+        repo.revparse_single("HEAD"),
+        &repo_dir_name,
+        "could not read HEAD".to_string(),
+    )?;
+    Ok(head.id().to_string())
+}
+
+// the paths in repo_dir that are modified, added or untracked, each with its status
+// letters as `git status --porcelain` shows them, e.g. " M src/lib.rs"
+pub fn local_changes(repo_dir: &PathBuf) -> Result<Vec<String>, Box<Error>> {
+    let repo_dir_name = repo_dir.to_string_lossy().to_string();
+    let repo = context(
+        Repository::open(repo_dir),
+        &repo_dir_name,
+        "could not open the checkout".to_string(),
+    )?;
+    let mut options = StatusOptions::new();
+    options.include_untracked(true);
+    let statuses = context(
+        repo.statuses(Some(&mut options)),
+        &repo_dir_name,
+        "could not read the status of the checkout".to_string(),
+    )?;
+    let mut changes = Vec::new();
+    for entry in statuses.iter() {
+        let path = String::from_utf8_lossy(entry.path_bytes()).to_string();
+        changes.push(format!("{} {}", status_letters(entry.status()), path));
+    }
+    Ok(changes)
+}
+
+fn status_letters(status: Status) -> String {
+    if status.contains(git2::STATUS_WT_NEW) {
+        return "??".to_string();
+    }
+    let index = if status.contains(git2::STATUS_INDEX_NEW) {
+        'A'
+    } else if status.contains(git2::STATUS_INDEX_MODIFIED) {
+        'M'
+    } else if status.contains(git2::STATUS_INDEX_DELETED) {
+        'D'
+    } else if status.contains(git2::STATUS_INDEX_RENAMED) {
+        'R'
+    } else if status.contains(git2::STATUS_INDEX_TYPECHANGE) {
+        'T'
+    } else {
+        ' '
+    };
+    let worktree = if status.contains(git2::STATUS_CONFLICTED) {
+        'U'
+    } else if status.contains(git2::STATUS_WT_MODIFIED) {
+        'M'
+    } else if status.contains(git2::STATUS_WT_DELETED) {
+        'D'
+    } else if status.contains(git2::STATUS_WT_RENAMED) {
+        'R'
+    } else if status.contains(git2::STATUS_WT_TYPECHANGE) {
+        'T'
+    } else {
+        ' '
+    };
+    format!("{}{}", index, worktree)
+}
+
+// the url repo_dir's origin remote fetches from
+pub fn remote_url(repo_dir: &PathBuf) -> Result<String, Box<Error>> {
+    let repo_dir_name = repo_dir.to_string_lossy().to_string();
+    let repo = context(
+        Repository::open(repo_dir),
+        &repo_dir_name,
+        "could not open the checkout".to_string(),
+    )?;
+    let remote = context(
+        repo.find_remote("origin"),
+        &repo_dir_name,
+        "could not find the origin remote".to_string(),
+    )?;
+    match remote.url() {
+        Some(url) => Ok(url.to_string()),
+        None => Err(Box::new(InstallError::git(
+            &repo_dir_name,
+            "the origin remote's url is not valid UTF-8".to_string(),
+        ))),
+    }
+}
+
+// the branch checked out in repo_dir, or "HEAD" when HEAD is detached, as
+// `git rev-parse --abbrev-ref HEAD` reports it
+pub fn current_branch(repo_dir: &PathBuf) -> Result<String, Box<Error>> {
+    let repo_dir_name = repo_dir.to_string_lossy().to_string();
+    let repo = context(
+        Repository::open(repo_dir),
+        &repo_dir_name,
+        "could not open the checkout".to_string(),
+    )?;
+    let head = context(
+        repo.head(),
+        &repo_dir_name,
+        "could not read HEAD".to_string(),
+    )?;
+    if !head.is_branch() {
+        return Ok("HEAD".to_string());
+    }
+    Ok(head.shorthand().unwrap_or("HEAD").to_string())
+}
+
+// A branch on origin is checked out as a local branch at origin's tip, fast-forwarding
+// the local branch if it already exists.  A tag or commit SHA leaves HEAD detached.
+fn checkout(repo: &Repository, target: &str) -> Result<(), Box<Error>> {
+    let remote_tip = match repo.find_branch(&format!("origin/{}", target), BranchType::Remote) {
+        Ok(remote_branch) => remote_branch.get().target(),
+        Err(_) => None,
+    };
+    match remote_tip {
+        Some(tip) => checkout_branch(repo, target, tip),
+        None => checkout_detached(repo, target),
+    }
+}
+
+fn checkout_branch(repo: &Repository, branch: &str, tip: Oid) -> Result<(), Box<Error>> {
+    let local_tip = match repo.find_branch(branch, BranchType::Local) {
+        Ok(local_branch) => local_branch.get().target(),
+        Err(_) => None,
+    };
+    let repo_dir_name = repo_name(repo);
+    if let Some(local) = local_tip {
+        let base = context(
+            repo.merge_base(local, tip),
+            &repo_dir_name,
+            format!("could not compare {} with origin/{}", branch, branch),
+        )?;
+        if local != tip && base != local {
+            return Err(Box::new(InstallError::git(
+                &repo_dir_name,
+                format!(
+                    "ERROR - local branch {} has diverged from origin/{} and can't be \
+                     fast-forwarded",
+                    branch, branch
+                ),
+            )));
+        }
+    }
+
+    // check out the new tree before moving HEAD, so the checkout compares against the
+    // files currently on disk and refuses to clobber local changes
+    let commit = context(
+        repo.find_commit(tip),
+        &repo_dir_name,
+        format!("could not find origin/{} at {}", branch, tip),
+    )?;
+    context(
+        repo.checkout_tree(commit.as_object(), Some(&mut checkout_options())),
+        &repo_dir_name,
+        format!("could not check out {}", branch),
+    )?;
+    let moved = match local_tip {
+        Some(_) => repo
+            .find_reference(&format!("refs/heads/{}", branch))
+            .and_then(|mut reference| {
+                reference
+                    .set_target(tip, "sky-install: fast-forward")
+                    .map(|_| ())
+            }),
+        None => repo
+            .branch(branch, &commit, false)
+            .and_then(|mut local_branch| {
+                local_branch.set_upstream(Some(&format!("origin/{}", branch)))
+            }),
+    };
+    context(
+        moved,
+        &repo_dir_name,
+        format!("could not move branch {} to {}", branch, tip),
+    )?;
+    context(
+        repo.set_head(&format!("refs/heads/{}", branch)),
+        &repo_dir_name,
+        format!("could not point HEAD at {}", branch),
+    )?;
+    println!("checked out branch {} at {}", branch, tip);
+    Ok(())
+}
+
+fn checkout_detached(repo: &Repository, target: &str) -> Result<(), Box<Error>> {
+    let object = match repo.revparse_single(target) {
+        Ok(object) => object,
+        Err(_) => {
+            return Err(Box::new(InstallError::git(
+                &repo_name(repo),
+                format!("ERROR - no branch, tag or commit named {}", target),
+            )))
+        }
+    };
+    let commit = context(
+        object.peel_to_commit(),
+        &repo_name(repo),
+        format!("{} does not name a commit", target),
+    )?;
+    context(
+        repo.checkout_tree(commit.as_object(), Some(&mut checkout_options())),
+        &repo_name(repo),
+        format!("could not check out {}", target),
+    )?;
+    context(
+        repo.set_head_detached(commit.id()),
+        &repo_name(repo),
+        format!("could not point HEAD at {}", commit.id()),
+    )?;
+    println!("checked out {} at {}", target, commit.id());
+    Ok(())
+}
+
+fn update_submodules(
+    repo: &Repository,
+    submodule_urls: &Vec<(String, Vec<String>)>,
+) -> Result<(), Box<Error>> {
+    let submodules = context(
+        repo.submodules(),
+        &repo_name(repo),
+        "could not read the submodules".to_string(),
+    )?;
+    for mut submodule in submodules {
+        let path = submodule.path().to_path_buf();
+        println!("updating submodule {:?}", path);
+        let urls = submodule_urls
+            .iter()
+            .find(|&&(ref submodule_path, _)| path == PathBuf::from(submodule_path))
+            .map(|&(_, ref urls)| urls.clone());
+        let submodule_repo = match urls {
+            Some(urls) => update_submodule_from(repo, &submodule, &urls)?,
+            None => update_submodule(&mut submodule)?,
+        };
+        update_submodules(&submodule_repo, submodule_urls)?;
+    }
+    Ok(())
+}
+
+// updates a submodule from the first of urls that can be reached.  A submodule that has
+// never been cloned is cleared out after each failure; one that has is only fetched into,
+// so a failure leaves it as it was.
+fn update_submodule_from(
+    repo: &Repository,
+    submodule: &git2::Submodule,
+    urls: &Vec<String>,
+) -> Result<Repository, Box<Error>> {
+    use platform;
+    use std::fs;
+
+    let name = submodule.name().unwrap_or_default().to_string();
+    let submodule_dir = match repo.workdir() {
+        Some(workdir) => workdir.join(submodule.path()),
+        None => submodule.path().to_path_buf(),
+    };
+    let git_module_dir = repo.path().join("modules").join(&name);
+    let fresh = !submodule_dir.join(".git").exists();
+    let mut last_error = None;
+    for url in urls.iter() {
+        // the url in .git/config wins over the one in .gitmodules
+        let configured = repo.find_submodule(&name).and_then(|mut submodule| {
+            submodule.init(false)?;
+            repo.config()?
+                .set_str(&format!("submodule.{}.url", name), url)
+        });
+        context(
+            configured,
+            &repo_name(repo),
+            format!("could not point submodule {} at {}", name, url),
+        )?;
+        println!("fetching submodule {} from {}", name, url);
+        let mut submodule = context(
+            repo.find_submodule(&name),
+            &repo_name(repo),
+            format!("could not find submodule {}", name),
+        )?;
+        match update_submodule(&mut submodule) {
+            Ok(submodule_repo) => return Ok(submodule_repo),
+            Err(err) => {
+                println!("\n{}", err);
+                if fresh {
+                    if submodule_dir.as_path().exists() {
+                        platform::remove_tree(&submodule_dir)?;
+                    }
+                    fs::create_dir_all(&submodule_dir)?;
+                    if git_module_dir.as_path().exists() {
+                        platform::remove_tree(&git_module_dir)?;
+                    }
+                }
+                last_error = Some(err);
+            }
+        }
+    }
+    match last_error {
+        Some(err) => Err(Box::new(err)),
+        None => Err(Box::new(InstallError::Usage(format!(
+            "no url configured for submodule {}",
+            name
+        )))),
+    }
+}
+
+fn update_submodule(submodule: &mut git2::Submodule) -> Result<Repository, InstallError> {
+    let mut options = SubmoduleUpdateOptions::new();
+    options.fetch(fetch_options());
+    options.checkout(checkout_options());
+    let url = submodule.url().unwrap_or_default().to_string();
+    context(
+        submodule.update(true, Some(&mut options)),
+        &url,
+        format!("could not update submodule {:?}", submodule.path()),
+    )?;
+    context(
+        submodule.open(),
+        &url,
+        format!("could not open submodule {:?}", submodule.path()),
+    )
+}
+
+fn fetch_options<'cb>() -> FetchOptions<'cb> {
+    use std::io::Write;
+
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.transfer_progress(|progress| {
+        print!(
+            "\rreceived {}/{} objects ({} KiB)",
+            progress.received_objects(),
+            progress.total_objects(),
+            progress.received_bytes() / 1024
+        );
+        let _ = ::std::io::stdout().flush();
+        true
+    });
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks);
+    // tags are fetched too so that they can be checked out by name
+    options.download_tags(AutotagOption::All);
+    // picks up the proxy setting, which Config::apply_proxy puts in the environment
+    let mut proxy = ProxyOptions::new();
+    proxy.auto();
+    options.proxy_options(proxy);
+    options
+}
+
+fn checkout_options<'cb>() -> CheckoutBuilder<'cb> {
+    use std::io::Write;
+
+    let mut options = CheckoutBuilder::new();
+    options.safe();
+    options.progress(|_path, completed, total| {
+        if total > 0 {
+            print!("\rchecking out files {}/{}", completed, total);
+            let _ = ::std::io::stdout().flush();
+            if completed == total {
+                println!("");
+            }
+        }
+    });
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn scratch_repo(test: &str) -> (PathBuf, Repository) {
+        let dir = env::temp_dir().join(format!("sky-install-{}-{}", ::std::process::id(), test));
+        if dir.as_path().exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        let repo = Repository::init(&dir).unwrap();
+        (dir, repo)
+    }
+
+    #[test]
+    fn reads_the_origin_url() {
+        let (dir, repo) = scratch_repo("remote-url");
+        assert!(remote_url(&dir).is_err());
+        repo.remote("origin", "https://example.com/SCAII.git")
+            .unwrap();
+        assert_eq!(remote_url(&dir).unwrap(), "https://example.com/SCAII.git");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn lists_local_changes_like_git_status() {
+        use super::super::git2::Signature;
+        use std::io::Write;
+        use std::path::Path;
+
+        let (dir, repo) = scratch_repo("local-changes");
+        assert!(local_changes(&dir).unwrap().is_empty());
+        fs::File::create(dir.join("tracked.txt"))
+            .unwrap()
+            .write_all(b"first")
+            .unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("tracked.txt")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("sky-install", "sky-install@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "first", &tree, &[])
+            .unwrap();
+        assert!(local_changes(&dir).unwrap().is_empty());
+
+        fs::File::create(dir.join("tracked.txt"))
+            .unwrap()
+            .write_all(b"second")
+            .unwrap();
+        fs::File::create(dir.join("new.txt")).unwrap();
+        let mut changes = local_changes(&dir).unwrap();
+        changes.sort();
+        assert_eq!(changes, vec![" M tracked.txt", "?? new.txt"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
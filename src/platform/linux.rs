@@ -3,6 +3,10 @@ extern crate git2;
 use std::error::Error;
 use std::path::{Path, PathBuf};
 
+// what the core and Sky-RTS libraries are called once installed into bin and backends/bin
+pub const CORE_LIBRARY_NAME: &'static str = "libscaii_core.so";
+pub const SKY_RTS_LIBRARY_NAME: &'static str = "libsky-rts.so";
+
 pub fn copy_built_rts(source_dir: PathBuf, target: PathBuf) -> Result<(), Box<Error>> {
     use platform::common;
     use std::fs;
@@ -11,7 +15,7 @@ pub fn copy_built_rts(source_dir: PathBuf, target: PathBuf) -> Result<(), Box<Er
         source_dir,
         target,
         "libbackend.so".to_string(),
-        "libsky-rts.so".to_string(),
+        SKY_RTS_LIBRARY_NAME.to_string(),
     )
 }
 
@@ -22,7 +26,7 @@ pub fn copy_built_core(source_dir: PathBuf, target: PathBuf) -> Result<(), Box<E
         source_dir,
         target,
         "libscaii_core.so".to_string(),
-        "libscaii_core.so".to_string(),
+        CORE_LIBRARY_NAME.to_string(),
     )
 }
 
@@ -36,10 +40,12 @@ pub fn run_command(command: &str, args: Vec<String>) -> Result<String, Box<Error
         c.arg(arg);
     }
     println!("running {:?}", c);
-    let output = c
-        .stdout(Stdio::inherit())
-        .output()
-        .expect(&format!("failed to launch command {}", command));
+    let output = c.stdout(Stdio::inherit()).output().map_err(|err| {
+        InstallError::Prerequisite(format!(
+            "could not run {} - is it installed? {}",
+            command, err
+        ))
+    })?;
     common::emit_error_output(&output);
     if output.status.success() {
         let result = String::from_utf8(output.stdout);
@@ -50,7 +56,8 @@ pub fn run_command(command: &str, args: Vec<String>) -> Result<String, Box<Error
             ))),
         }
     } else {
-        Err(Box::new(InstallError::new(
+        Err(Box::new(InstallError::command(
+            &format!("{:?}", c),
             String::from_utf8_lossy(&output.stderr).to_string(),
         )))
     }
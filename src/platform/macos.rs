@@ -3,6 +3,10 @@ extern crate git2;
 use std::error::Error;
 use std::path::{Path, PathBuf};
 
+// what the core and Sky-RTS libraries are called once installed into bin and backends/bin
+pub const CORE_LIBRARY_NAME: &'static str = "scaii_core.dylib";
+pub const SKY_RTS_LIBRARY_NAME: &'static str = "sky-rts.dylib";
+
 pub fn copy_built_core(source_dir: PathBuf, target: PathBuf) -> Result<(), Box<Error>> {
     use platform::common;
     use std::fs;
@@ -12,7 +16,7 @@ pub fn copy_built_core(source_dir: PathBuf, target: PathBuf) -> Result<(), Box<E
         source_dir,
         target,
         "libscaii_core.dylib".to_string(),
-        "scaii_core.dylib".to_string(),
+        CORE_LIBRARY_NAME.to_string(),
     )
 }
 
@@ -24,7 +28,7 @@ pub fn copy_built_rts(source_dir: PathBuf, target: PathBuf) -> Result<(), Box<Er
         source_dir,
         target,
         "libbackend.dylib".to_string(),
-        "sky-rts.dylib".to_string(),
+        SKY_RTS_LIBRARY_NAME.to_string(),
     )
 }
 
@@ -45,13 +49,12 @@ pub fn run_command(command: &str, args: Vec<String>) -> Result<String, Box<Error
     }
     println!("{:?}", env::current_dir());
     println!("running {:?}", c);
-    let output = c
-        .stdout(Stdio::inherit())
-        .output()
-        .expect(&String::as_str(&format!(
-            "failed to launch command {}",
-            command
-        )));
+    let output = c.stdout(Stdio::inherit()).output().map_err(|err| {
+        InstallError::Prerequisite(format!(
+            "could not run {} - is it installed? {}",
+            command, err
+        ))
+    })?;
 
     common::emit_error_output(&output);
     if output.status.success() {
@@ -63,7 +66,8 @@ pub fn run_command(command: &str, args: Vec<String>) -> Result<String, Box<Error
             ))),
         }
     } else {
-        Err(Box::new(InstallError::new(
+        Err(Box::new(InstallError::command(
+            &format!("{:?}", c),
             String::from_utf8_lossy(&output.stderr).to_string(),
         )))
     }
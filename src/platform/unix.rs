@@ -1,6 +1,10 @@
 extern crate git2;
 
+use config::Config;
 use error::InstallError;
+use lockfile::{
+    locked_artifact, Lockfile, ARTIFACT_CLOSURE_LIBRARY, ARTIFACT_PROTOBUF_JS, ARTIFACT_SCAII,
+};
 use std::error::Error;
 use std::fmt::Debug;
 use std::path::{Path, PathBuf};
@@ -16,6 +20,9 @@ pub mod os_specific;
 #[cfg(target_os = "linux")]
 pub mod os_specific;
 
+#[path = "git.rs"]
+mod git;
+
 pub use os_specific::*;
 use os_specific::{copy_built_core, run_command};
 
@@ -27,78 +34,116 @@ pub fn remove_tree<P: AsRef<Path> + Debug>(dir: P) -> Result<(), Box<Error>> {
     args.push(dir.as_ref().to_str().unwrap().to_string());
     let result_string = run_command(&command, args)?;
     if result_string != "" {
-        return Err(Box::new(InstallError::new(format!(
-            "ERROR trying to delete files {}",
-            result_string
-        ))));
+        return Err(Box::new(InstallError::filesystem(
+            &dir.as_ref().to_path_buf(),
+            format!("ERROR trying to delete files {}", result_string),
+        )));
     }
     Ok(())
 }
 
-pub fn get_core(install_dir: PathBuf, command_args: &Args) -> Result<(), Box<Error>> {
-    use self::git2::Repository;
+pub fn link_dir(target: &PathBuf, link: &PathBuf) -> Result<(), Box<Error>> {
+    use std::os::unix::fs::symlink;
+
+    println!("linking {:?} to {:?}", link, target);
+    symlink(target, link)?;
+    Ok(())
+}
+
+pub fn remove_link(link: &PathBuf) -> Result<(), Box<Error>> {
+    use std::fs;
+
+    fs::remove_file(link)?;
+    Ok(())
+}
+
+pub fn get_core(
+    install_dir: PathBuf,
+    command_args: &Args,
+    config: &Config,
+    lock: Option<&Lockfile>,
+) -> Result<(), Box<Error>> {
     use std::env;
 
     println!("installing core...");
     let orig_dir_pathbuf = env::current_dir()?;
-    println!("{:?}", orig_dir_pathbuf);
-    env::set_current_dir(install_dir.clone())?;
-
-    let command: String = "git".to_string();
-    let mut args: Vec<String> = Vec::new();
-    args.push("clone".to_string());
-    args.push("https://github.com/SCAII/SCAII.git".to_string());
-    let result_string = run_command(&command, args)?;
-    verify_git_clone_success(&result_string)?;
-
-    // let url = "https://github.com/SCAII/SCAII.git";
-    // let _repo = match Repository::clone(url, install_dir.clone().to_str().unwrap()) {
-    //     Ok(repo) => repo,
-    //     Err(e) => panic!("failed to clone: {}", e),
-    // };
-    let mut scaii_dir = install_dir.clone();
-    scaii_dir.push("SCAII".to_string());
-    if command_args.flag_branch {
-        println!("{:?}", scaii_dir);
-        env::set_current_dir(scaii_dir.clone())?;
-        checkout(command_args.arg_branch_name.clone())?;
-    }
-    ensure_google_closure_lib_installed(scaii_dir.clone())?;
-    install_protobuf_javascript_lib(scaii_dir)?;
+    let scaii_dir = install_dir.join("SCAII");
+    clone_repo(
+        &config.repo_urls(ARTIFACT_SCAII, SCAII_URL),
+        &scaii_dir,
+        clone_target(command_args),
+        &submodule_urls(config),
+    )?;
+    let closure = locked_artifact(lock, ARTIFACT_CLOSURE_LIBRARY)?;
+    ensure_google_closure_lib_installed(scaii_dir.clone(), config, closure)?;
+    let protobuf_js = locked_artifact(lock, ARTIFACT_PROTOBUF_JS)?;
+    ensure_protobuf_javascript_lib_installed(scaii_dir, config, protobuf_js)?;
     env::set_current_dir(orig_dir_pathbuf)?;
     Ok(())
 }
 
-pub fn get_sky_rts(install_dir: PathBuf, command_args: &Args) -> Result<(), Box<Error>> {
-    use self::git2::Repository;
-    use std::env;
+// urls are tried in order, the first being the repository and the rest its mirrors
+pub fn clone_repo(
+    urls: &Vec<String>,
+    dest: &PathBuf,
+    target: Option<&str>,
+    submodule_urls: &Vec<(String, Vec<String>)>,
+) -> Result<(), Box<Error>> {
+    git::clone(urls, dest, target, submodule_urls)
+}
 
-    println!("installing Sky-RTS...");
-    let orig_dir_pathbuf = env::current_dir()?;
-    println!("{:?}", orig_dir_pathbuf);
-    env::set_current_dir(install_dir.clone())?;
+// fetches origin and moves the checkout to target, fast-forwarding it if target is a
+// branch.  Local changes are never overwritten.
+pub fn update_checkout(
+    repo_dir: &PathBuf,
+    target: &str,
+    submodule_urls: &Vec<(String, Vec<String>)>,
+) -> Result<(), Box<Error>> {
+    git::update(repo_dir, target, submodule_urls)
+}
+
+pub fn get_head_commit(repo_dir: &PathBuf) -> Result<String, Box<Error>> {
+    git::head_commit(repo_dir)
+}
+
+pub fn get_current_branch(repo_dir: &PathBuf) -> Result<String, Box<Error>> {
+    git::current_branch(repo_dir)
+}
+
+pub fn get_remote_url(repo_dir: &PathBuf) -> Result<String, Box<Error>> {
+    git::remote_url(repo_dir)
+}
 
-    let command: String = "git".to_string();
+pub fn get_local_changes(repo_dir: &PathBuf) -> Result<Vec<String>, Box<Error>> {
+    git::local_changes(repo_dir)
+}
+
+// megabytes free on the filesystem holding path, which must exist
+pub fn free_space_mb(path: &PathBuf) -> Result<u64, Box<Error>> {
     let mut args: Vec<String> = Vec::new();
-    args.push("clone".to_string());
-    args.push("https://github.com/SCAII/Sky-RTS.git".to_string());
-    let result_string = run_command(&command, args)?;
-    verify_git_clone_success(&result_string)?;
-
-    // let url = "https://github.com/SCAII/Sky-RTS.git";
-    // let _repo = match Repository::clone(url, install_dir.clone().to_str().unwrap()) {
-    //     Ok(repo) => repo,
-    //     Err(e) => panic!("failed to clone: {}", e),
-    // };
-    if command_args.flag_branch {
-        let mut sky_rts_dir = install_dir.clone();
-        sky_rts_dir.push("Sky-RTS".to_string());
-        println!("{:?}", sky_rts_dir);
-        env::set_current_dir(sky_rts_dir.clone())?;
-        checkout(command_args.arg_branch_name.clone())?;
+    args.push("-Pk".to_string());
+    args.push(path.to_string_lossy().to_string());
+    let output = capture_command("df", args)?;
+    // the line after the header: filesystem, size, used, available, ... in KiB
+    let available = output
+        .lines()
+        .nth(1)
+        .and_then(|line| line.split_whitespace().nth(3))
+        .and_then(|available| available.parse::<u64>().ok());
+    match available {
+        Some(available) => Ok(available / 1024),
+        None => Err(Box::new(InstallError::command(
+            "df -Pk",
+            format!("unexpected output {:?}", output),
+        ))),
     }
-    env::set_current_dir(orig_dir_pathbuf)?;
-    Ok(())
+}
+
+// the C compiler cargo links with
+pub fn find_build_tools() -> Result<String, Box<Error>> {
+    let output = capture_command("cc", vec!["--version".to_string()])
+        .map_err(|_| InstallError::Prerequisite("no C compiler (cc) was found".to_string()))?;
+    Ok(output.lines().next().unwrap_or("cc").to_string())
 }
 
 pub fn copy_recursive<P: AsRef<Path> + Debug>(source: PathBuf, dest: P) -> Result<(), Box<Error>> {
@@ -110,10 +155,10 @@ pub fn copy_recursive<P: AsRef<Path> + Debug>(source: PathBuf, dest: P) -> Resul
     args.push(dest.as_ref().to_str().unwrap().to_string());
     let result_string = run_command(&command, args)?;
     if !(result_string == "".to_string()) {
-        return Err(Box::new(InstallError::new(format!(
-            "ERROR - problem copying files {:?}",
-            result_string
-        ))));
+        return Err(Box::new(InstallError::filesystem(
+            &source,
+            format!("ERROR - problem copying files {:?}", result_string),
+        )));
     }
     Ok(())
 }
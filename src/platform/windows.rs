@@ -1,4 +1,8 @@
+use config::Config;
 use error::InstallError;
+use lockfile::{
+    locked_artifact, Lockfile, ARTIFACT_CLOSURE_LIBRARY, ARTIFACT_PROTOBUF_JS, ARTIFACT_SCAII,
+};
 use std::error::Error;
 use std::fmt::Debug;
 use std::path::{Path, PathBuf};
@@ -6,6 +10,10 @@ use Args;
 
 use platform::common::*;
 
+// what the core and Sky-RTS libraries are called once installed into bin and backends/bin
+pub const CORE_LIBRARY_NAME: &'static str = "scaii_core.dll";
+pub const SKY_RTS_LIBRARY_NAME: &'static str = "sky-rts.dll";
+
 // fs::remove_dir_all has issues in windows, so need to shell out
 pub fn remove_tree<P: AsRef<Path> + Debug>(dir: P) -> Result<(), Box<Error>> {
     //rmdir c:\test /s /q
@@ -17,42 +25,326 @@ pub fn remove_tree<P: AsRef<Path> + Debug>(dir: P) -> Result<(), Box<Error>> {
     args.push("/q".to_string());
     let result_string = run_command(&protoc_hack(command), args)?;
     if result_string != "" {
-        return Err(Box::new(InstallError::new(format!(
-            "ERROR trying to delete files {}",
-            result_string
-        ))));
+        return Err(Box::new(InstallError::filesystem(
+            &dir.as_ref().to_path_buf(),
+            format!("ERROR trying to delete files {}", result_string),
+        )));
     }
     Ok(())
 }
+// directory symlinks need admin rights on windows, junctions don't
+pub fn link_dir(target: &PathBuf, link: &PathBuf) -> Result<(), Box<Error>> {
+    //mklink /J c:\link c:\target
+    println!("linking {:?} to {:?}", link, target);
+    let command: String = "mklink".to_string();
+    let mut args: Vec<String> = Vec::new();
+    args.push("/J".to_string());
+    args.push(link.to_str().unwrap().to_string());
+    args.push(target.to_str().unwrap().to_string());
+    run_command(&command, args)?;
+    Ok(())
+}
+
+pub fn remove_link(link: &PathBuf) -> Result<(), Box<Error>> {
+    // rmdir without /s removes the junction but leaves its target alone
+    let command: String = "rmdir".to_string();
+    let mut args: Vec<String> = Vec::new();
+    args.push(link.to_str().unwrap().to_string());
+    run_command(&command, args)?;
+    Ok(())
+}
+
 //shelling out to git on windows due to build error on Jed's windows laptop trying to build git2
 // (cmake invocation of cl.exe uses forward slashes for path - likely explanation for dll adjacent to cl.exe
 //  not being found)
-pub fn get_core(install_dir: PathBuf, command_args: &Args) -> Result<(), Box<Error>> {
+pub fn get_core(
+    install_dir: PathBuf,
+    command_args: &Args,
+    config: &Config,
+    lock: Option<&Lockfile>,
+) -> Result<(), Box<Error>> {
     use platform::common;
     use std::env;
 
     println!("installing core...");
     let orig_dir_pathbuf = env::current_dir()?;
+    let scaii_dir = install_dir.join("SCAII");
+    clone_repo(
+        &config.repo_urls(ARTIFACT_SCAII, SCAII_URL),
+        &scaii_dir,
+        clone_target(command_args),
+        &common::submodule_urls(config),
+    )?;
+    let closure = locked_artifact(lock, ARTIFACT_CLOSURE_LIBRARY)?;
+    ensure_google_closure_lib_installed(scaii_dir.clone(), config, closure)?;
+    let protobuf_js = locked_artifact(lock, ARTIFACT_PROTOBUF_JS)?;
+    common::ensure_protobuf_javascript_lib_installed(scaii_dir, config, protobuf_js)?;
+    env::set_current_dir(orig_dir_pathbuf)?;
+    Ok(())
+}
+
+// run_command turns git's failure status into an error, so nothing has to be read out of
+// git's output.  urls are tried in order, the first being the repository and the rest
+// its mirrors.
+pub fn clone_repo(
+    urls: &Vec<String>,
+    dest: &PathBuf,
+    target: Option<&str>,
+    submodule_urls: &Vec<(String, Vec<String>)>,
+) -> Result<(), Box<Error>> {
+    let mut last_error = None;
+    for url in urls.iter() {
+        println!("cloning {} into {:?}...", url, dest);
+        let cloned = run_git(
+            url,
+            vec![
+                "clone".to_string(),
+                url.to_string(),
+                dest.to_str().unwrap().to_string(),
+            ],
+        );
+        match cloned {
+            Ok(_) => {
+                if let Some(target) = target {
+                    run_git_in(dest, vec!["checkout".to_string(), target.to_string()])?;
+                }
+                return update_submodules(dest, submodule_urls);
+            }
+            Err(err) => {
+                // clear out what was fetched so the next mirror starts afresh
+                if dest.as_path().exists() {
+                    remove_tree(dest)?;
+                }
+                last_error = Some(err);
+            }
+        }
+    }
+    match last_error {
+        Some(err) => Err(err),
+        None => Err(Box::new(InstallError::Usage(format!(
+            "no url configured to clone {:?} from",
+            dest
+        )))),
+    }
+}
+
+// fetches origin and moves the checkout to target, fast-forwarding it if target is a
+// branch.  --ff-only makes git refuse to update a branch that has diverged, and checkout
+// refuses to clobber local changes.
+pub fn update_checkout(
+    repo_dir: &PathBuf,
+    target: &str,
+    submodule_urls: &Vec<(String, Vec<String>)>,
+) -> Result<(), Box<Error>> {
+    println!("fetching {:?}...", repo_dir);
+    run_git_in(
+        repo_dir,
+        vec![
+            "fetch".to_string(),
+            "--tags".to_string(),
+            "origin".to_string(),
+        ],
+    )?;
+    run_git_in(repo_dir, vec!["checkout".to_string(), target.to_string()])?;
+    let remote_branch = format!("refs/remotes/origin/{}", target);
+    let is_branch = run_git_in(
+        repo_dir,
+        vec![
+            "rev-parse".to_string(),
+            "--verify".to_string(),
+            "--quiet".to_string(),
+            remote_branch,
+        ],
+    )
+    .is_ok();
+    if is_branch {
+        run_git_in(
+            repo_dir,
+            vec![
+                "merge".to_string(),
+                "--ff-only".to_string(),
+                format!("origin/{}", target),
+            ],
+        )?;
+    }
+    update_submodules(repo_dir, submodule_urls)
+}
+
+// submodules in submodule_urls are updated from the first of their urls that can be
+// reached, the rest from .gitmodules.  Each submodule is assumed to be named after its
+// path, as git submodule add names them.
+fn update_submodules(
+    repo_dir: &PathBuf,
+    submodule_urls: &Vec<(String, Vec<String>)>,
+) -> Result<(), Box<Error>> {
+    for &(ref path, ref urls) in submodule_urls.iter() {
+        update_submodule_from(repo_dir, path, urls)?;
+    }
+    run_git_in(
+        repo_dir,
+        vec![
+            "submodule".to_string(),
+            "update".to_string(),
+            "--init".to_string(),
+            "--recursive".to_string(),
+        ],
+    )?;
+    Ok(())
+}
+
+fn update_submodule_from(
+    repo_dir: &PathBuf,
+    path: &str,
+    urls: &Vec<String>,
+) -> Result<(), Box<Error>> {
+    use std::fs;
+
+    let submodule_dir = repo_dir.join(path);
+    let git_module_dir = repo_dir.join(".git").join("modules").join(path);
+    // a submodule that has been cloned before is only fetched into, so a failure leaves
+    // it as it was
+    let fresh = !submodule_dir.join(".git").exists();
+    let mut last_error = None;
+    for url in urls.iter() {
+        run_git_in(
+            repo_dir,
+            vec![
+                "submodule".to_string(),
+                "init".to_string(),
+                "--".to_string(),
+                path.to_string(),
+            ],
+        )?;
+        // the url in .git/config wins over the one in .gitmodules
+        run_git_in(
+            repo_dir,
+            vec![
+                "config".to_string(),
+                format!("submodule.{}.url", path),
+                url.to_string(),
+            ],
+        )?;
+        println!("fetching submodule {} from {}", path, url);
+        let updated = run_git_in(
+            repo_dir,
+            vec![
+                "submodule".to_string(),
+                "update".to_string(),
+                "--".to_string(),
+                path.to_string(),
+            ],
+        );
+        match updated {
+            Ok(_) => return Ok(()),
+            Err(err) => {
+                if fresh {
+                    if submodule_dir.as_path().exists() {
+                        remove_tree(&submodule_dir)?;
+                    }
+                    fs::create_dir_all(&submodule_dir)?;
+                    if git_module_dir.as_path().exists() {
+                        remove_tree(&git_module_dir)?;
+                    }
+                }
+                last_error = Some(err);
+            }
+        }
+    }
+    match last_error {
+        Some(err) => Err(err),
+        None => Err(Box::new(InstallError::Usage(format!(
+            "no url configured for submodule {}",
+            path
+        )))),
+    }
+}
+
+// run_command passes git's output through to the console, so it is captured instead
+pub fn get_head_commit(repo_dir: &PathBuf) -> Result<String, Box<Error>> {
+    let command: String = "git".to_string();
+    let mut args: Vec<String> = Vec::new();
+    args.push("-C".to_string());
+    args.push(repo_dir.to_str().unwrap().to_string());
+    args.push("rev-parse".to_string());
+    args.push("HEAD".to_string());
+    let result_string = capture_command(&command, args)?;
+    Ok(result_string.trim().to_string())
+}
 
-    println!("{:?}", orig_dir_pathbuf);
-    env::set_current_dir(&install_dir)?;
+pub fn get_current_branch(repo_dir: &PathBuf) -> Result<String, Box<Error>> {
     let command: String = "git".to_string();
     let mut args: Vec<String> = Vec::new();
-    args.push("clone".to_string());
-    args.push("https://github.com/SCAII/SCAII.git".to_string());
-    let result_string = run_command(&command, args)?;
-    verify_git_clone_success(&result_string)?;
-    let mut scaii_dir = install_dir;
-    scaii_dir.push("SCAII".to_string());
-    if command_args.flag_branch {
-        println!("{:?}", scaii_dir);
-        env::set_current_dir(scaii_dir.clone())?;
-        checkout(command_args.arg_branch_name.clone())?;
+    args.push("-C".to_string());
+    args.push(repo_dir.to_str().unwrap().to_string());
+    args.push("rev-parse".to_string());
+    args.push("--abbrev-ref".to_string());
+    args.push("HEAD".to_string());
+    let result_string = capture_command(&command, args)?;
+    Ok(result_string.trim().to_string())
+}
+
+pub fn get_remote_url(repo_dir: &PathBuf) -> Result<String, Box<Error>> {
+    let command: String = "git".to_string();
+    let mut args: Vec<String> = Vec::new();
+    args.push("-C".to_string());
+    args.push(path_arg(repo_dir)?);
+    args.push("config".to_string());
+    args.push("--get".to_string());
+    args.push("remote.origin.url".to_string());
+    let result_string = capture_command(&command, args)?;
+    Ok(result_string.trim().to_string())
+}
+
+// the paths `git status` reports as modified, added or untracked in repo_dir, each with
+// its status letters, e.g. " M src/lib.rs"
+pub fn get_local_changes(repo_dir: &PathBuf) -> Result<Vec<String>, Box<Error>> {
+    let command: String = "git".to_string();
+    let mut args: Vec<String> = Vec::new();
+    args.push("-C".to_string());
+    args.push(path_arg(repo_dir)?);
+    args.push("status".to_string());
+    args.push("--porcelain".to_string());
+    let result_string = capture_command(&command, args)?;
+    Ok(result_string
+        .lines()
+        .filter(|line| line.trim() != "")
+        .map(|line| line.to_string())
+        .collect())
+}
+
+// path as a command line argument, which it can only be passed as if it is valid unicode
+fn path_arg(path: &PathBuf) -> Result<String, Box<Error>> {
+    match path.to_str() {
+        Some(path_str) => Ok(path_str.to_string()),
+        None => Err(Box::new(InstallError::filesystem(
+            path,
+            "the path is not valid unicode, so it can't be passed to git".to_string(),
+        ))),
+    }
+}
+
+fn run_git_in(repo_dir: &PathBuf, args: Vec<String>) -> Result<String, Box<Error>> {
+    let mut git_args: Vec<String> = Vec::new();
+    git_args.push("-C".to_string());
+    git_args.push(path_arg(repo_dir)?);
+    git_args.extend(args);
+    run_git(&repo_dir.to_string_lossy(), git_args)
+}
+
+// a failed git command is a git failure of repo, the path or url it worked on, like the
+// libgit2 failures on other platforms.  A missing git stays a missing prerequisite.
+fn run_git(repo: &str, args: Vec<String>) -> Result<String, Box<Error>> {
+    use error::{error_kind, ErrorKind};
+
+    let command: String = "git".to_string();
+    let action = format!("git {} failed", args.join(" "));
+    match run_command(&command, args) {
+        Ok(output) => Ok(output),
+        Err(err) => match error_kind(&*err) {
+            ErrorKind::Prerequisite => Err(err),
+            _ => Err(Box::new(InstallError::git(repo, action).caused_by(err))),
+        },
     }
-    ensure_google_closure_lib_installed(scaii_dir.clone())?;
-    common::install_protobuf_javascript_lib(scaii_dir)?;
-    env::set_current_dir(orig_dir_pathbuf)?;
-    Ok(())
 }
 
 pub fn copy_built_core(source_dir: PathBuf, target: PathBuf) -> Result<(), Box<Error>> {
@@ -61,10 +353,68 @@ pub fn copy_built_core(source_dir: PathBuf, target: PathBuf) -> Result<(), Box<E
         source_dir,
         target,
         "scaii_core.dll".to_string(),
-        "scaii_core.dll".to_string(),
+        CORE_LIBRARY_NAME.to_string(),
     )
 }
 
+pub fn copy_built_rts(source_dir: PathBuf, target: PathBuf) -> Result<(), Box<Error>> {
+    // rename backend.dll to sky-rts.dll
+    copy_source_named(
+        source_dir,
+        target,
+        "backend.dll".to_string(),
+        SKY_RTS_LIBRARY_NAME.to_string(),
+    )
+}
+
+// megabytes free on the drive holding path, which must exist
+pub fn free_space_mb(path: &PathBuf) -> Result<u64, Box<Error>> {
+    let mut args: Vec<String> = Vec::new();
+    args.push("-NoProfile".to_string());
+    args.push("-Command".to_string());
+    args.push(format!(
+        "(Get-Item -LiteralPath '{}').PSDrive.Free",
+        path.to_string_lossy()
+    ));
+    let output = capture_command("powershell", args)?;
+    match output.trim().parse::<u64>() {
+        Ok(free) => Ok(free / 1048576),
+        Err(_) => Err(Box::new(InstallError::command(
+            "powershell Get-Item",
+            format!("unexpected output {:?}", output),
+        ))),
+    }
+}
+
+// the Visual Studio C++ build tools the msvc toolchain links with, as vswhere (installed
+// with Visual Studio 2017 and later) finds them
+pub fn find_build_tools() -> Result<String, Box<Error>> {
+    use std::env;
+
+    let program_files =
+        env::var("ProgramFiles(x86)").unwrap_or("C:\\Program Files (x86)".to_string());
+    let vswhere = PathBuf::from(program_files)
+        .join("Microsoft Visual Studio")
+        .join("Installer")
+        .join("vswhere.exe");
+    let mut args: Vec<String> = Vec::new();
+    args.push("-products".to_string());
+    args.push("*".to_string());
+    args.push("-requires".to_string());
+    args.push("Microsoft.VisualStudio.Component.VC.Tools.x86.x64".to_string());
+    args.push("-property".to_string());
+    args.push("installationPath".to_string());
+    let output = capture_command(&vswhere.to_string_lossy(), args).map_err(|_| {
+        InstallError::Prerequisite("the Visual Studio installer was not found".to_string())
+    })?;
+    match output.lines().next() {
+        Some(path) if path.trim() != "" => Ok(format!("C++ build tools in {}", path.trim())),
+        _ => Err(Box::new(InstallError::Prerequisite(
+            "no Visual Studio install has the C++ build tools".to_string(),
+        ))),
+    }
+}
+
 pub fn copy_recursive<P: AsRef<Path> + Debug>(source: PathBuf, dest: P) -> Result<(), Box<Error>> {
     println!("copy {:?} to {:?}", source, dest);
     let command: String = "xcopy".to_string();
@@ -90,13 +440,12 @@ pub fn run_command(command: &str, args: Vec<String>) -> Result<String, Box<Error
         c.arg(arg);
     }
     println!("running {:?}", c);
-    let output = c
-        .stdout(Stdio::inherit())
-        .output()
-        .expect(&String::as_str(&format!(
-            "failed to launch command {}",
-            command
-        )));
+    let output = c.stdout(Stdio::inherit()).output().map_err(|err| {
+        InstallError::Prerequisite(format!(
+            "could not run {} - is it installed? {}",
+            command, err
+        ))
+    })?;
     common::emit_error_output(&output);
     if output.status.success() {
         let result = String::from_utf8(output.stdout);
@@ -107,7 +456,8 @@ pub fn run_command(command: &str, args: Vec<String>) -> Result<String, Box<Error
             ))),
         }
     } else {
-        Err(Box::new(InstallError::new(
+        Err(Box::new(InstallError::command(
+            &format!("{:?}", c),
             String::from_utf8_lossy(&output.stderr).to_string(),
         )))
     }
@@ -0,0 +1,169 @@
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use config::Config;
+use error::InstallError;
+use manifest::Manifest;
+
+// install --prebuilt downloads what building would have produced instead of compiling
+// SCAII.  A release index, a toml file, lists the builds published for each branch,
+// profile and target:
+//   [[build]]
+//   branch = "master"
+//   profile = "release"
+//   target = "x86_64-unknown-linux-gnu"
+//   commit = "<SCAII commit>"
+//   url = "https://.../scaii-master-release-x86_64-unknown-linux-gnu.tar.gz"
+//   sha256 = "<sha-256 of the archive>"
+// Each archive holds the bin, glue and backends dirs of a version dir, laid out as
+// build_core, build_sky_rts and copy_execs would lay them out.  The index is fetched
+// from [repos.prebuilt-index] in config.toml, else the default below.
+pub const PREBUILT_INDEX_REPO: &'static str = "prebuilt-index";
+const PREBUILT_INDEX_URL: &'static str =
+    "https://github.com/SCAII/SCAII/releases/download/prebuilt/index.toml";
+const INDEX_FILENAME: &'static str = "prebuilt-index.toml";
+// where the archive is unpacked in the staging dir before its dirs are moved up
+const UNPACK_DIRNAME: &'static str = "prebuilt";
+const OUTPUT_ITEMS: [&'static str; 3] = ["bin", "glue", "backends"];
+
+#[cfg(target_os = "windows")]
+pub const EXE_SUFFIX: &'static str = ".exe";
+#[cfg(not(target_os = "windows"))]
+pub const EXE_SUFFIX: &'static str = "";
+
+#[derive(Debug, Default, Deserialize)]
+struct PrebuiltIndex {
+    #[serde(default, rename = "build")]
+    builds: Vec<PrebuiltBuild>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PrebuiltBuild {
+    pub branch: String,
+    pub profile: String,
+    pub target: String,
+    pub commit: String,
+    // the commit Sky-RTS was built from, when it isn't the SCAII commit
+    #[serde(default)]
+    pub sky_rts_commit: Option<String>,
+    pub url: String,
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+    pub sha256: String,
+}
+
+// the target triple prebuilt builds are published for that runs here
+pub fn target_triple() -> String {
+    use std::env::consts::{ARCH, OS};
+
+    match OS {
+        "linux" => format!("{}-unknown-linux-gnu", ARCH),
+        "macos" => format!("{}-apple-darwin", ARCH),
+        "windows" => format!("{}-pc-windows-msvc", ARCH),
+        other => format!("{}-unknown-{}", ARCH, other),
+    }
+}
+
+// the build the index lists for branch and profile on this target, if there is one.  An
+// index that can't be fetched counts as listing nothing, since SCAII can still be built.
+pub fn find(
+    config: &Config,
+    branch: &str,
+    compile_type: &String,
+) -> Result<Option<PrebuiltBuild>, Box<Error>> {
+    use platform::common;
+    use std::io::Read;
+    use toml;
+    use versions;
+
+    let urls = config.repo_urls(PREBUILT_INDEX_REPO, PREBUILT_INDEX_URL);
+    let index_path = config.dot_scaii.join(INDEX_FILENAME);
+    common::ensure_dir_exists(&config.dot_scaii)?;
+    println!("...fetching the prebuilt index");
+    if let Err(err) = common::download_from_mirrors(&urls, &index_path, config) {
+        println!("could not fetch the prebuilt index: {}", err);
+        return Ok(None);
+    }
+    let mut contents = String::new();
+    fs::File::open(&index_path)?.read_to_string(&mut contents)?;
+    fs::remove_file(&index_path)?;
+    let index: PrebuiltIndex = toml::from_str(&contents).map_err(|err| {
+        InstallError::network(&urls[0], "the prebuilt index is not valid toml".to_string())
+            .caused_by(Box::new(err))
+    })?;
+    let profile = versions::profile_name(compile_type);
+    let target = target_triple();
+    // a later entry is a newer build of the same thing
+    Ok(index
+        .builds
+        .into_iter()
+        .filter(|build| {
+            build.branch == branch && build.profile == profile && build.target == target
+        })
+        .last())
+}
+
+// downloads and verifies build, then moves its bin, glue and backends dirs into
+// dest_root (a version dir or the staging dir) and records them in the install manifest
+pub fn install(
+    build: &PrebuiltBuild,
+    dest_root: &PathBuf,
+    config: &Config,
+) -> Result<(), Box<Error>> {
+    use manifest::{COMPONENT_CORE, COMPONENT_EXECS, COMPONENT_SKY_RTS};
+    use platform;
+    use platform::common;
+
+    let mut urls = vec![build.url.clone()];
+    urls.extend(build.mirrors.iter().cloned());
+    let (unpacked, _) = common::install_archive(
+        dest_root,
+        &urls,
+        0,
+        "",
+        UNPACK_DIRNAME,
+        config,
+        Some(build.sha256.clone()),
+    )?;
+    let core_library = PathBuf::from("bin").join(platform::CORE_LIBRARY_NAME);
+    let sky_rts_library = PathBuf::from("backends")
+        .join("bin")
+        .join(platform::SKY_RTS_LIBRARY_NAME);
+    let expected = vec![
+        core_library.clone(),
+        PathBuf::from("bin").join(format!("replay{}", EXE_SUFFIX)),
+        PathBuf::from("bin").join("viz"),
+        PathBuf::from("bin").join("cfg.toml"),
+        PathBuf::from("glue").join("python"),
+        sky_rts_library.clone(),
+        PathBuf::from("backends").join("sky-rts").join("maps"),
+    ];
+    for path in expected.iter() {
+        if !unpacked.join(path).exists() {
+            return Err(Box::new(InstallError::network(
+                &build.url,
+                format!("the prebuilt archive has no {:?}", path),
+            )));
+        }
+    }
+    for item in OUTPUT_ITEMS.iter() {
+        fs::rename(unpacked.join(item), dest_root.join(item))?;
+    }
+    platform::remove_tree(&unpacked)?;
+
+    // owned as a build would own them: the core library and glue by core, the backends
+    // and the Sky-RTS glue copied over it by sky-rts, and the rest of bin by execs
+    let mut manifest = Manifest::new(build.commit.clone());
+    manifest.record_tree(dest_root, &dest_root.join(&core_library), COMPONENT_CORE)?;
+    let sky_rts_glue = dest_root
+        .join("glue")
+        .join("python")
+        .join("scaii")
+        .join("env");
+    manifest.record_tree(dest_root, &sky_rts_glue, COMPONENT_SKY_RTS)?;
+    manifest.record_tree(dest_root, &dest_root.join("glue"), COMPONENT_CORE)?;
+    manifest.record_tree(dest_root, &dest_root.join("backends"), COMPONENT_SKY_RTS)?;
+    manifest.record_tree(dest_root, &dest_root.join("bin"), COMPONENT_EXECS)?;
+    manifest.save(dest_root)
+}
@@ -0,0 +1,271 @@
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use manifest::{Manifest, MANIFEST_FILENAME};
+use platform;
+use platform::common;
+use versions;
+use versions::INFO_FILENAME;
+
+// Installs are built into ~/.scaii/staging and only swapped into their version dir once
+// every step has succeeded, so a failed build, a failed download or a Ctrl-C leaves the
+// installed versions untouched.  Whatever gets replaced is kept in ~/.scaii/previous,
+// along with which version was active beforehand, for rollback.
+pub const STAGING_DIRNAME: &'static str = "staging";
+pub const PREVIOUS_DIRNAME: &'static str = "previous";
+const ROLLBACK_DIRNAME: &'static str = "rollback";
+
+// in ~/.scaii/previous, naming the version the saved items came from and the version
+// that was active before the swap
+const PREVIOUS_VERSION_FILENAME: &'static str = "version";
+const PREVIOUS_ACTIVE_FILENAME: &'static str = "active";
+
+// present only while a swap is moving directories around; names the incoming dir and
+// the version dir, then lists the items being swapped in
+const SWAP_MARKER_FILENAME: &'static str = "swap-in-progress";
+
+// everything in a version dir that belongs to one install
+const INSTALL_ITEMS: [&'static str; 6] = [
+    "git",
+    "bin",
+    "glue",
+    "backends",
+    MANIFEST_FILENAME,
+    INFO_FILENAME,
+];
+
+// items holding installed files that users may have added their own files to
+const OUTPUT_ITEMS: [&'static str; 3] = ["bin", "glue", "backends"];
+
+// returns a fresh, empty staging dir, discarding anything left by an interrupted install
+pub fn prepare_staging_dir(dot_scaii: &PathBuf) -> Result<PathBuf, Box<Error>> {
+    discard_staging_dir(dot_scaii)?;
+    let staging = dot_scaii.join(STAGING_DIRNAME);
+    common::ensure_dir_exists(&staging)?;
+    Ok(staging)
+}
+
+pub fn discard_staging_dir(dot_scaii: &PathBuf) -> Result<(), Box<Error>> {
+    let staging = dot_scaii.join(STAGING_DIRNAME);
+    if staging.as_path().exists() {
+        platform::remove_tree(&staging)?;
+    }
+    Ok(())
+}
+
+pub fn discard_previous_install(dot_scaii: &PathBuf) -> Result<(), Box<Error>> {
+    let previous = dot_scaii.join(PREVIOUS_DIRNAME);
+    if previous.as_path().exists() {
+        platform::remove_tree(&previous)?;
+    }
+    Ok(())
+}
+
+// moves the staged install into the named version dir, keeping whatever it replaces
+// (and the name of the version active before it) as the previous install
+pub fn promote_staging_dir(
+    dot_scaii: &PathBuf,
+    version_name: &str,
+    active_before: Option<String>,
+) -> Result<(), Box<Error>> {
+    let staging = dot_scaii.join(STAGING_DIRNAME);
+    let version_dir = versions::get_version_dir(dot_scaii, version_name)?;
+    common::ensure_dir_exists(&version_dir)?;
+    carry_over_untracked_files(&version_dir, &staging)?;
+    swap_in(dot_scaii, version_name, STAGING_DIRNAME, active_before)
+}
+
+// puts the previous install back into its version dir, keeping what it replaces as the
+// previous install so that a second rollback undoes the first.  Returns the version
+// that was active before the install being rolled back.
+pub fn rollback(
+    dot_scaii: &PathBuf,
+    active_before: Option<String>,
+) -> Result<Option<String>, Box<Error>> {
+    use error::InstallError;
+
+    let previous = dot_scaii.join(PREVIOUS_DIRNAME);
+    let version_name = read_name_file(&previous.join(PREVIOUS_VERSION_FILENAME))?;
+    let version_name = match version_name {
+        Some(name) => name,
+        None => {
+            return Err(Box::new(InstallError::Prerequisite(
+                "there is no previous install to roll back to.".to_string(),
+            )))
+        }
+    };
+    let restored_active = read_name_file(&previous.join(PREVIOUS_ACTIVE_FILENAME))?;
+    let rollback = dot_scaii.join(ROLLBACK_DIRNAME);
+    if rollback.as_path().exists() {
+        platform::remove_tree(&rollback)?;
+    }
+    fs::rename(&previous, &rollback)?;
+    swap_in(dot_scaii, &version_name, ROLLBACK_DIRNAME, active_before)?;
+    Ok(restored_active)
+}
+
+// undoes a swap that was interrupted part way through, restoring the install that was
+// live before it started
+pub fn recover_interrupted_swap(dot_scaii: &PathBuf) -> Result<(), Box<Error>> {
+    use std::io::Read;
+
+    let marker = dot_scaii.join(SWAP_MARKER_FILENAME);
+    if !marker.as_path().exists() {
+        return Ok(());
+    }
+    println!("recovering from an interrupted install...");
+    let mut contents = String::new();
+    fs::File::open(&marker)?.read_to_string(&mut contents)?;
+    let mut lines = contents.lines();
+    let incoming_name = lines.next().unwrap_or(STAGING_DIRNAME).to_string();
+    let version_name = lines.next().unwrap_or("").to_string();
+    let incoming = dot_scaii.join(&incoming_name);
+    let version_dir = versions::get_version_dir(dot_scaii, &version_name)?;
+    let previous = dot_scaii.join(PREVIOUS_DIRNAME);
+    common::ensure_dir_exists(&incoming)?;
+
+    for item in lines {
+        let live_item = version_dir.join(item);
+        let incoming_item = incoming.join(item);
+        let previous_item = previous.join(item);
+        // the incoming copy is gone only if it was already moved into place
+        if !incoming_item.exists() && live_item.exists() {
+            fs::rename(&live_item, &incoming_item)?;
+        }
+        if previous_item.exists() && !live_item.exists() {
+            fs::rename(&previous_item, &live_item)?;
+        }
+    }
+
+    if incoming_name == STAGING_DIRNAME {
+        platform::remove_tree(&incoming)?;
+    } else {
+        // a rollback was interrupted, so the incoming install is the old previous one
+        discard_previous_install(dot_scaii)?;
+        fs::rename(&incoming, &previous)?;
+    }
+    fs::remove_file(&marker)?;
+    Ok(())
+}
+
+// moves the items found in dot_scaii/incoming_name into the version dir, moving the
+// items they replace into dot_scaii/previous.  Items that the incoming install doesn't
+// have (e.g. the git checkout on reinstall) are left where they are.
+fn swap_in(
+    dot_scaii: &PathBuf,
+    version_name: &str,
+    incoming_name: &str,
+    active_before: Option<String>,
+) -> Result<(), Box<Error>> {
+    use std::io::Write;
+
+    let incoming = dot_scaii.join(incoming_name);
+    let version_dir = versions::get_version_dir(dot_scaii, version_name)?;
+    let previous = dot_scaii.join(PREVIOUS_DIRNAME);
+    let items: Vec<&str> = INSTALL_ITEMS
+        .iter()
+        .cloned()
+        .filter(|item| incoming.join(item).exists())
+        .collect();
+
+    discard_previous_install(dot_scaii)?;
+    common::ensure_dir_exists(&previous)?;
+    common::ensure_dir_exists(&version_dir)?;
+    write_name_file(&previous.join(PREVIOUS_VERSION_FILENAME), version_name)?;
+    if let Some(name) = active_before {
+        write_name_file(&previous.join(PREVIOUS_ACTIVE_FILENAME), &name)?;
+    }
+
+    let marker = dot_scaii.join(SWAP_MARKER_FILENAME);
+    {
+        let mut marker_file = fs::File::create(&marker)?;
+        writeln!(marker_file, "{}", incoming_name)?;
+        writeln!(marker_file, "{}", version_name)?;
+        for item in items.iter() {
+            writeln!(marker_file, "{}", item)?;
+        }
+        marker_file.sync_all()?;
+    }
+
+    for item in items.iter() {
+        let live_item = version_dir.join(item);
+        if live_item.exists() {
+            fs::rename(&live_item, previous.join(item))?;
+        }
+    }
+    for item in items.iter() {
+        fs::rename(incoming.join(item), version_dir.join(item))?;
+    }
+
+    fs::remove_file(&marker)?;
+    platform::remove_tree(&incoming)?;
+    Ok(())
+}
+
+fn read_name_file(path: &PathBuf) -> Result<Option<String>, Box<Error>> {
+    use std::io::Read;
+
+    if !path.as_path().exists() {
+        return Ok(None);
+    }
+    let mut contents = String::new();
+    fs::File::open(path)?.read_to_string(&mut contents)?;
+    Ok(Some(contents.trim().to_string()))
+}
+
+fn write_name_file(path: &PathBuf, name: &str) -> Result<(), Box<Error>> {
+    use std::io::Write;
+
+    let mut file = fs::File::create(path)?;
+    write!(file, "{}", name)?;
+    Ok(())
+}
+
+// copies files the user added to the installed version (anything its manifest doesn't
+// list) into the staged install, so swapping whole directories doesn't lose them
+fn carry_over_untracked_files(version_dir: &PathBuf, staging: &PathBuf) -> Result<(), Box<Error>> {
+    let manifest = match Manifest::load(version_dir)? {
+        Some(manifest) => manifest,
+        // without a manifest every file looks untracked, and the previous install is
+        // still kept for rollback, so there is nothing to carry over
+        None => return Ok(()),
+    };
+    for item in OUTPUT_ITEMS.iter() {
+        let mut files: Vec<PathBuf> = Vec::new();
+        collect_untracked(version_dir, &version_dir.join(item), &manifest, &mut files)?;
+        for file in files {
+            let relative = file.strip_prefix(version_dir)?.to_path_buf();
+            let dest = staging.join(&relative);
+            if dest.exists() {
+                continue;
+            }
+            if let Some(parent) = dest.parent() {
+                common::ensure_dir_exists(&parent.to_path_buf())?;
+            }
+            println!("keeping untracked file {:?}", relative);
+            fs::copy(&file, &dest)?;
+        }
+    }
+    Ok(())
+}
+
+fn collect_untracked(
+    dot_scaii: &PathBuf,
+    path: &PathBuf,
+    manifest: &Manifest,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), Box<Error>> {
+    if !path.as_path().exists() {
+        return Ok(());
+    }
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            collect_untracked(dot_scaii, &entry?.path(), manifest, files)?;
+        }
+    } else if !manifest.contains(dot_scaii, path) {
+        files.push(path.clone());
+    }
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use manifest::Manifest;
+use versions::VersionInfo;
+
+// 'status' describes the active version: what it was built from and when, whether each
+// part SCAII needs is present and still matches what the install wrote, and whether the
+// checkout it was built from has been changed since.
+pub fn run(dot_scaii: &PathBuf) -> Result<(), Box<Error>> {
+    use versions;
+
+    let name = match versions::read_active(dot_scaii)? {
+        Some(name) => name,
+        None => {
+            let installed = versions::list_versions(dot_scaii)?;
+            if installed.is_empty() {
+                println!("Nothing is installed in {:?}.", dot_scaii);
+            } else {
+                println!(
+                    "No version is active - {} installed, see 'sky-install list'.",
+                    installed.len()
+                );
+            }
+            return Ok(());
+        }
+    };
+    let version_dir = versions::get_version_dir(dot_scaii, &name)?;
+    println!("Active version {}    {:?}", name, version_dir);
+    let info = VersionInfo::load(&version_dir)?;
+    match info {
+        Some(ref info) => print_version_info(info),
+        None => println!("  no install.toml - installed by an older sky-install"),
+    }
+    println!("Components");
+    print_components(&version_dir)?;
+    print_checkout(&version_dir, &info);
+    Ok(())
+}
+
+fn print_version_info(info: &VersionInfo) {
+    println!(
+        "  SCAII      branch {}, commit {}",
+        info.branch, info.commit
+    );
+    if let Some(ref rev) = info.rev {
+        println!("             pinned to {}", rev);
+    }
+    let sky_rts_commit = match info.sky_rts_commit {
+        Some(ref commit) => commit.clone(),
+        None => info.commit.clone(),
+    };
+    if sky_rts_commit == info.commit {
+        println!("  Sky-RTS    part of SCAII, commit {}", sky_rts_commit);
+    } else {
+        println!("  Sky-RTS    commit {}", sky_rts_commit);
+    }
+    if let Some(ref rev) = info.sky_rts_rev {
+        println!("             pinned to {}", rev);
+    }
+    println!("  profile    {}", info.profile);
+    println!("  installed  {}", describe_age(info.installed_at));
+    if let Some(ref source) = info.source {
+        println!("  source     {}", source);
+    }
+    if let Some(ref url) = info.prebuilt {
+        println!("  prebuilt   {}", url);
+    }
+}
+
+fn print_components(version_dir: &PathBuf) -> Result<(), Box<Error>> {
+    use platform;
+    use prebuilt::EXE_SUFFIX;
+
+    let components = vec![
+        ("scaii_core", format!("bin/{}", platform::CORE_LIBRARY_NAME)),
+        ("replay", format!("bin/replay{}", EXE_SUFFIX)),
+        (
+            "sky-rts",
+            format!("backends/bin/{}", platform::SKY_RTS_LIBRARY_NAME),
+        ),
+        ("glue", "glue/python".to_string()),
+        ("viz", "bin/viz".to_string()),
+        ("maps", "backends/sky-rts/maps".to_string()),
+    ];
+    let manifest = Manifest::load(version_dir)?;
+    for (label, relative) in components {
+        let path = relative
+            .split("/")
+            .fold(version_dir.clone(), |path, part| path.join(part));
+        let state = if !path.as_path().exists() {
+            "MISSING".to_string()
+        } else {
+            match manifest {
+                Some(ref manifest) => {
+                    describe_integrity(manifest.check_files(version_dir, &relative)?)
+                }
+                None => "present, with no manifest to check it against".to_string(),
+            }
+        };
+        println!("  {:<10} {:<32} {}", label, relative, state);
+    }
+    Ok(())
+}
+
+fn describe_integrity(counts: (usize, usize, usize)) -> String {
+    let (recorded, missing, modified) = counts;
+    if recorded == 0 {
+        return "present, but not in the manifest".to_string();
+    }
+    let mut problems = Vec::new();
+    if missing > 0 {
+        problems.push(format!("{} of {} files missing", missing, recorded));
+    }
+    if modified > 0 {
+        problems.push(format!("{} of {} files modified", modified, recorded));
+    }
+    if problems.is_empty() {
+        "ok".to_string()
+    } else {
+        problems.join(", ")
+    }
+}
+
+// the checkout the version was built from: the user's own for --source installs, else
+// the one kept in the version dir.  Prebuilt versions have none.
+fn print_checkout(version_dir: &PathBuf, info: &Option<VersionInfo>) {
+    use platform;
+
+    let scaii_dir = match *info {
+        Some(ref info) if info.prebuilt.is_some() => {
+            println!("Checkout   none - installed prebuilt");
+            return;
+        }
+        Some(VersionInfo {
+            source: Some(ref source),
+            ..
+        }) => PathBuf::from(source),
+        _ => version_dir.join("git").join("SCAII"),
+    };
+    if !scaii_dir.as_path().exists() {
+        println!("Checkout   {:?} is MISSING", scaii_dir);
+        return;
+    }
+    match platform::get_local_changes(&scaii_dir) {
+        Ok(ref changes) if changes.is_empty() => {
+            println!("Checkout   {:?} has no local modifications", scaii_dir)
+        }
+        Ok(changes) => {
+            println!(
+                "Checkout   {:?} has {} local modifications",
+                scaii_dir,
+                changes.len()
+            );
+            for change in changes {
+                println!("    {}", change);
+            }
+        }
+        Err(err) => println!("Checkout   {:?} could not be checked: {}", scaii_dir, err),
+    }
+}
+
+// e.g. "3 days ago"
+fn describe_age(installed_at: u64) -> String {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    if installed_at == 0 {
+        return "at an unknown time".to_string();
+    }
+    let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs(),
+        Err(_) => 0,
+    };
+    let secs = if now > installed_at {
+        now - installed_at
+    } else {
+        0
+    };
+    if secs < 60 {
+        return "just now".to_string();
+    }
+    let (count, unit) = if secs < 60 * 60 {
+        (secs / 60, "minute")
+    } else if secs < 60 * 60 * 24 {
+        (secs / (60 * 60), "hour")
+    } else {
+        (secs / (60 * 60 * 24), "day")
+    };
+    if count == 1 {
+        format!("1 {} ago", unit)
+    } else {
+        format!("{} {}s ago", count, unit)
+    }
+}
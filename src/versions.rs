@@ -0,0 +1,283 @@
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use manifest::MANIFEST_FILENAME;
+use platform;
+use platform::common;
+
+// Each install lives in ~/.scaii/versions/<name>, keyed by branch and build profile.
+// ~/.scaii/bin, glue and backends are links into the active version, so the paths users
+// put on PATH and PYTHONPATH never change, and ~/.scaii/active names the active version.
+pub const VERSIONS_DIRNAME: &'static str = "versions";
+pub const INFO_FILENAME: &'static str = "install.toml";
+const ACTIVE_FILENAME: &'static str = "active";
+
+// the name given to an install made before versions existed
+const LEGACY_VERSION_NAME: &'static str = "legacy";
+
+// the parts of a version that are reachable from ~/.scaii while it is active
+const LINKED_ITEMS: [&'static str; 3] = ["bin", "glue", "backends"];
+
+// everything an install made before versions existed wrote directly into ~/.scaii
+const LEGACY_ITEMS: [&'static str; 5] = ["git", "bin", "glue", "backends", MANIFEST_FILENAME];
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct VersionInfo {
+    pub name: String,
+    pub branch: String,
+    pub profile: String,
+    pub commit: String,
+    // seconds since the unix epoch
+    pub installed_at: u64,
+    // the user's own checkout for versions installed with --source, which is never
+    // removed along with the version
+    #[serde(default)]
+    pub source: Option<String>,
+    // the --rev and --sky-rts-rev the version was installed from, if any
+    #[serde(default)]
+    pub rev: Option<String>,
+    #[serde(default)]
+    pub sky_rts_rev: Option<String>,
+    // the commit Sky-RTS was built from, which is the SCAII commit unless Sky-RTS is
+    // checked out as its own repository
+    #[serde(default)]
+    pub sky_rts_commit: Option<String>,
+    // the archive a version installed with --prebuilt came from.  Such a version has no
+    // checkout to rebuild or update.
+    #[serde(default)]
+    pub prebuilt: Option<String>,
+}
+
+impl VersionInfo {
+    pub fn new(name: &str, branch: &str, compile_type: &String, commit: String) -> VersionInfo {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let installed_at = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs(),
+            Err(_) => 0,
+        };
+        VersionInfo {
+            name: name.to_string(),
+            branch: branch.to_string(),
+            profile: profile_name(compile_type).to_string(),
+            commit: commit,
+            installed_at: installed_at,
+            source: None,
+            rev: None,
+            sky_rts_rev: None,
+            sky_rts_commit: None,
+            prebuilt: None,
+        }
+    }
+
+    pub fn load(version_dir: &PathBuf) -> Result<Option<VersionInfo>, Box<Error>> {
+        use std::io::Read;
+        use toml;
+
+        let path = version_dir.join(INFO_FILENAME);
+        if !path.as_path().exists() {
+            return Ok(None);
+        }
+        let mut contents = String::new();
+        fs::File::open(&path)?.read_to_string(&mut contents)?;
+        let info: VersionInfo = toml::from_str(&contents)?;
+        Ok(Some(info))
+    }
+
+    pub fn save(&self, version_dir: &PathBuf) -> Result<(), Box<Error>> {
+        use std::io::Write;
+        use toml;
+
+        let contents = toml::to_string(self)?;
+        let mut file = fs::File::create(version_dir.join(INFO_FILENAME))?;
+        file.write_all(contents.as_bytes())?;
+        Ok(())
+    }
+}
+
+pub fn profile_name(compile_type: &String) -> &'static str {
+    if compile_type != "" {
+        "release"
+    } else {
+        "debug"
+    }
+}
+
+// the cargo flag building the named profile, the reverse of profile_name
+pub fn compile_type(profile: &str) -> String {
+    if profile == "debug" {
+        "".to_string()
+    } else {
+        "--release".to_string()
+    }
+}
+
+// branch names may contain '/', which can't appear in a directory name
+pub fn version_name(branch: &str, compile_type: &String) -> String {
+    let safe_branch: String = branch
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '.' || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}-{}", safe_branch, profile_name(compile_type))
+}
+
+pub fn get_versions_dir(dot_scaii: &PathBuf) -> Result<PathBuf, Box<Error>> {
+    let versions_dir = dot_scaii.join(VERSIONS_DIRNAME);
+    common::ensure_dir_exists(&versions_dir)?;
+    Ok(versions_dir)
+}
+
+pub fn get_version_dir(dot_scaii: &PathBuf, name: &str) -> Result<PathBuf, Box<Error>> {
+    Ok(get_versions_dir(dot_scaii)?.join(name))
+}
+
+// the dir of a version named on the command line, which must be one 'list' shows.  A
+// name like ".." would otherwise resolve outside versions/ and take ~/.scaii with it.
+pub fn get_installed_version_dir(dot_scaii: &PathBuf, name: &str) -> Result<PathBuf, Box<Error>> {
+    use error::InstallError;
+
+    let safe_name =
+        name != "" && !name.contains('/') && !name.contains('\\') && !name.contains("..");
+    if !safe_name || !list_versions(dot_scaii)?.contains(&name.to_string()) {
+        return Err(Box::new(InstallError::Usage(format!(
+            "version {} is not installed - run 'list' to see installed versions.",
+            name
+        ))));
+    }
+    get_version_dir(dot_scaii, name)
+}
+
+pub fn read_active(dot_scaii: &PathBuf) -> Result<Option<String>, Box<Error>> {
+    use std::io::Read;
+
+    let path = dot_scaii.join(ACTIVE_FILENAME);
+    if !path.as_path().exists() {
+        return Ok(None);
+    }
+    let mut contents = String::new();
+    fs::File::open(&path)?.read_to_string(&mut contents)?;
+    let name = contents.trim().to_string();
+    if name == "" {
+        Ok(None)
+    } else {
+        Ok(Some(name))
+    }
+}
+
+pub fn list_versions(dot_scaii: &PathBuf) -> Result<Vec<String>, Box<Error>> {
+    let mut names: Vec<String> = Vec::new();
+    for entry in fs::read_dir(get_versions_dir(dot_scaii)?)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            names.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+// points ~/.scaii/bin, glue and backends at the named version
+pub fn activate(dot_scaii: &PathBuf, name: &str) -> Result<(), Box<Error>> {
+    use std::io::Write;
+
+    let version_dir = get_installed_version_dir(dot_scaii, name)?;
+    deactivate(dot_scaii)?;
+    for item in LINKED_ITEMS.iter() {
+        let target = version_dir.join(item);
+        if target.as_path().exists() {
+            platform::link_dir(&target, &dot_scaii.join(item))?;
+        }
+    }
+    let mut active_file = fs::File::create(dot_scaii.join(ACTIVE_FILENAME))?;
+    write!(active_file, "{}", name)?;
+    println!("{} is now the active version.", name);
+    Ok(())
+}
+
+pub fn deactivate(dot_scaii: &PathBuf) -> Result<(), Box<Error>> {
+    for item in LINKED_ITEMS.iter() {
+        let link = dot_scaii.join(item);
+        if is_link(&link) {
+            platform::remove_link(&link)?;
+        }
+    }
+    let active = dot_scaii.join(ACTIVE_FILENAME);
+    if active.as_path().exists() {
+        fs::remove_file(&active)?;
+    }
+    Ok(())
+}
+
+// moves an install made before versions existed into versions/legacy and activates it
+pub fn migrate_legacy_install(dot_scaii: &PathBuf) -> Result<(), Box<Error>> {
+    let found = LEGACY_ITEMS.iter().any(|item| {
+        let path = dot_scaii.join(item);
+        path.as_path().exists() && !is_link(&path)
+    });
+    if !found {
+        return Ok(());
+    }
+    println!("moving existing install into {}...", LEGACY_VERSION_NAME);
+    let version_dir = get_version_dir(dot_scaii, LEGACY_VERSION_NAME)?;
+    common::ensure_dir_exists(&version_dir)?;
+    for item in LEGACY_ITEMS.iter() {
+        let path = dot_scaii.join(item);
+        if path.as_path().exists() && !is_link(&path) {
+            fs::rename(&path, version_dir.join(item))?;
+        }
+    }
+    activate(dot_scaii, LEGACY_VERSION_NAME)
+}
+
+fn is_link(path: &PathBuf) -> bool {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata.file_type().is_symlink(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::InstallError;
+    use std::env;
+
+    #[test]
+    fn only_installed_versions_can_be_named() {
+        let dot_scaii = env::temp_dir().join(format!(
+            "sky-install-{}-version-names",
+            ::std::process::id()
+        ));
+        fs::create_dir_all(dot_scaii.join(VERSIONS_DIRNAME).join("master-release")).unwrap();
+        assert_eq!(
+            get_installed_version_dir(&dot_scaii, "master-release").unwrap(),
+            dot_scaii.join(VERSIONS_DIRNAME).join("master-release")
+        );
+        for name in [
+            "",
+            "..",
+            "../versions",
+            "master-release/..",
+            "a\\b",
+            "missing",
+        ]
+        .iter()
+        {
+            match get_installed_version_dir(&dot_scaii, name) {
+                Err(err) => match err.downcast_ref::<InstallError>() {
+                    Some(&InstallError::Usage(_)) => {}
+                    _ => panic!("expected a usage error for {:?}, got {}", name, err),
+                },
+                Ok(dir) => panic!("accepted {:?} as {:?}", name, dir),
+            }
+        }
+        fs::remove_dir_all(&dot_scaii).unwrap();
+    }
+}